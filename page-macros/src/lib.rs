@@ -0,0 +1,393 @@
+// The `grammar!` macro, which turns PEG notation into page combinators.
+//
+// grammar! {
+//     pub enum Calc;
+//     sum <- num ("+" num)* ;
+//     num <- ['0'-'9']+ ;
+// }
+//
+// expands to an `enum Calc { Sum, Num }` implementing `Identifier`, with
+// `Calc::rules()` building every rule, labeled with its variant, and
+// `Calc::Sum.parser()` as a shorthand for a single one, out of a grammar
+// built once per thread. Rules are made of
+// string and character literals, character classes (`[^'a'-'z' '_']`), `.`,
+// rule names and groups, combined with `/`, `&`, `!`, `*`, `+` and `?`.
+
+use std::collections::HashMap;
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+struct Error(Span, String);
+
+type Tokens<'t> = std::iter::Peekable<std::slice::Iter<'t, TokenTree>>;
+
+enum Expr {
+    Choice(Vec<Expr>),
+    Sequence(Vec<Expr>),
+    And(Box<Expr>),
+    Not(Box<Expr>),
+    Star(Box<Expr>),
+    Plus(Box<Expr>),
+    Question(Box<Expr>),
+    Rule(Ident),
+    Str(Literal),
+    Char(Literal),
+    Class {
+        negated: bool,
+        ranges: Vec<(Literal, Literal)>,
+    },
+    Any,
+}
+
+fn is_punct(t: Option<&&TokenTree>, c: char) -> bool {
+    matches!(t, Some(TokenTree::Punct(p)) if p.as_char() == c)
+}
+
+fn span_of(t: Option<&&TokenTree>) -> Span {
+    t.map(|t| t.span()).unwrap_or_else(Span::call_site)
+}
+
+fn choice(tokens: &mut Tokens) -> Result<Expr, Error> {
+    let mut alternatives = vec![sequence(tokens)?];
+    while is_punct(tokens.peek(), '/') {
+        tokens.next();
+        alternatives.push(sequence(tokens)?);
+    }
+    Ok(match alternatives.len() {
+        1 => alternatives.pop().unwrap(),
+        _ => Expr::Choice(alternatives),
+    })
+}
+
+fn sequence(tokens: &mut Tokens) -> Result<Expr, Error> {
+    let mut items = vec![];
+    while tokens.peek().is_some() && !is_punct(tokens.peek(), '/') {
+        items.push(prefix(tokens)?);
+    }
+    match items.len() {
+        0 => Err(Error(
+            span_of(tokens.peek()),
+            "Expected an expression!".to_owned(),
+        )),
+        1 => Ok(items.pop().unwrap()),
+        _ => Ok(Expr::Sequence(items)),
+    }
+}
+
+fn prefix(tokens: &mut Tokens) -> Result<Expr, Error> {
+    if is_punct(tokens.peek(), '&') {
+        tokens.next();
+        return Ok(Expr::And(Box::new(suffix(tokens)?)));
+    }
+    if is_punct(tokens.peek(), '!') {
+        tokens.next();
+        return Ok(Expr::Not(Box::new(suffix(tokens)?)));
+    }
+    suffix(tokens)
+}
+
+fn suffix(tokens: &mut Tokens) -> Result<Expr, Error> {
+    let p = primary(tokens)?;
+    let op = match tokens.peek() {
+        Some(TokenTree::Punct(op)) => op.as_char(),
+        _ => return Ok(p),
+    };
+    let p = match op {
+        '*' => Expr::Star(Box::new(p)),
+        '+' => Expr::Plus(Box::new(p)),
+        '?' => Expr::Question(Box::new(p)),
+        _ => return Ok(p),
+    };
+    tokens.next();
+    Ok(p)
+}
+
+fn primary(tokens: &mut Tokens) -> Result<Expr, Error> {
+    match tokens.next() {
+        Some(TokenTree::Ident(i)) => Ok(Expr::Rule(i.clone())),
+        Some(TokenTree::Punct(p)) if p.as_char() == '.' => Ok(Expr::Any),
+        Some(TokenTree::Literal(l)) => {
+            let s = l.to_string();
+            if s.starts_with('\'') {
+                Ok(Expr::Char(l.clone()))
+            } else if s.starts_with('"') || s.starts_with('r') {
+                let raw = s.strip_prefix('r').map(|r| r.trim_matches('#'));
+                if s == "\"\"" || raw == Some("\"\"") {
+                    return Err(Error(l.span(), "Empty literal!".to_owned()));
+                }
+                Ok(Expr::Str(l.clone()))
+            } else {
+                Err(Error(
+                    l.span(),
+                    "Expected a string or character literal!".to_owned(),
+                ))
+            }
+        }
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis => {
+            let inner: Vec<TokenTree> = g.stream().into_iter().collect();
+            let mut inner = inner.iter().peekable();
+            let e = choice(&mut inner)?;
+            match inner.next() {
+                Some(t) => Err(Error(t.span(), "Unexpected token!".to_owned())),
+                None => Ok(e),
+            }
+        }
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Bracket => class(g),
+        t => Err(Error(
+            span_of(t.as_ref()),
+            "Expected a rule, literal, class, group or `.`!".to_owned(),
+        )),
+    }
+}
+
+fn class(g: &Group) -> Result<Expr, Error> {
+    let inner: Vec<TokenTree> = g.stream().into_iter().collect();
+    let mut inner = inner.iter().peekable();
+    let negated = is_punct(inner.peek(), '^');
+    if negated {
+        inner.next();
+    }
+    let char_literal = |t: Option<&TokenTree>| match t {
+        Some(TokenTree::Literal(l)) if l.to_string().starts_with('\'') => Ok(l.clone()),
+        t => Err(Error(
+            span_of(t.as_ref()),
+            "Expected a character literal!".to_owned(),
+        )),
+    };
+    let mut ranges = vec![];
+    while inner.peek().is_some() {
+        let first = char_literal(inner.next())?;
+        if is_punct(inner.peek(), '-') {
+            inner.next();
+            let last = char_literal(inner.next())?;
+            ranges.push((first, last));
+        } else {
+            ranges.push((first.clone(), first));
+        }
+    }
+    if ranges.is_empty() {
+        return Err(Error(g.span(), "Empty character class!".to_owned()));
+    }
+    Ok(Expr::Class { negated, ranges })
+}
+
+fn variant(rule: &str) -> String {
+    rule.split('_')
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut c = w.chars();
+            let first = c.next().unwrap().to_uppercase();
+            first.chain(c).collect::<String>()
+        })
+        .collect()
+}
+
+fn check(e: &Expr, rules: &HashMap<String, usize>) -> Result<(), Error> {
+    match e {
+        Expr::Choice(v) | Expr::Sequence(v) => v.iter().try_for_each(|e| check(e, rules)),
+        Expr::And(e) | Expr::Not(e) | Expr::Star(e) | Expr::Plus(e) | Expr::Question(e) => {
+            check(e, rules)
+        }
+        Expr::Rule(i) if !rules.contains_key(&i.to_string()) => {
+            Err(Error(i.span(), format!("Undefined rule `{}`!", i)))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn expand(e: &Expr, rules: &HashMap<String, usize>) -> String {
+    let binary = |v: &[Expr], op: &str| {
+        let mut v = v.iter();
+        let mut s = expand(v.next().unwrap(), rules);
+        for e in v {
+            s = format!("{}.{}({})", s, op, expand(e, rules));
+        }
+        s
+    };
+    match e {
+        Expr::Choice(v) => binary(v, "or"),
+        Expr::Sequence(v) => binary(v, "seq"),
+        Expr::And(e) => format!("{}.ensure()", expand(e, rules)),
+        Expr::Not(e) => format!("{}.avoid()", expand(e, rules)),
+        Expr::Star(e) => format!("{}.multiple()", expand(e, rules)),
+        Expr::Plus(e) => format!("{}.atleast_once()", expand(e, rules)),
+        Expr::Question(e) => format!("{}.maybe()", expand(e, rules)),
+        Expr::Rule(i) => format!("rules[{}].parser()", rules[&i.to_string()]),
+        Expr::Str(l) => format!("::page::primitives::pstr({})", l),
+        Expr::Char(l) => format!("::page::primitives::pchar({})", l),
+        Expr::Class { negated, ranges } => {
+            let pattern = ranges
+                .iter()
+                .map(|(first, last)| format!("{}..={}", first, last))
+                .collect::<Vec<_>>()
+                .join(" | ");
+            format!(
+                "::page::primitives::ppredicate(|s: &str| {{ \
+                    let c = s.chars().next().unwrap(); \
+                    ({}matches!(c, {}), c.len_utf8()) \
+                }})",
+                if *negated { "!" } else { "" },
+                pattern
+            )
+        }
+        Expr::Any => "::page::primitives::pany()".to_owned(),
+    }
+}
+
+fn expand_grammar(input: TokenStream) -> Result<String, Error> {
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    let mut tokens = tokens.iter().peekable();
+
+    let mut visibility = String::new();
+    while let Some(t) = tokens.peek() {
+        match t {
+            TokenTree::Ident(i) if i.to_string() == "enum" => break,
+            _ => visibility.push_str(&format!("{} ", tokens.next().unwrap())),
+        }
+    }
+    tokens.next();
+    let name = match tokens.next() {
+        Some(TokenTree::Ident(i)) => i.clone(),
+        t => {
+            return Err(Error(
+                span_of(t.as_ref()),
+                "Expected `enum Name;` before the rules!".to_owned(),
+            ))
+        }
+    };
+    if !is_punct(tokens.next().as_ref(), ';') {
+        return Err(Error(
+            name.span(),
+            "Expected `;` after the enum's name!".to_owned(),
+        ));
+    }
+
+    let mut rules: Vec<(Ident, Vec<TokenTree>)> = vec![];
+    while let Some(t) = tokens.next() {
+        let rule = match t {
+            TokenTree::Ident(i) => i.clone(),
+            t => return Err(Error(t.span(), "Expected a rule name!".to_owned())),
+        };
+        match (tokens.next(), tokens.next()) {
+            (Some(TokenTree::Punct(l)), Some(TokenTree::Punct(r)))
+                if l.as_char() == '<' && l.spacing() == Spacing::Joint && r.as_char() == '-' => {}
+            _ => {
+                return Err(Error(
+                    rule.span(),
+                    "Expected `<-` after the rule name!".to_owned(),
+                ))
+            }
+        }
+        let mut body = vec![];
+        loop {
+            match tokens.next() {
+                Some(TokenTree::Punct(p)) if p.as_char() == ';' => break,
+                Some(t) => body.push(t.clone()),
+                None => {
+                    return Err(Error(
+                        rule.span(),
+                        "Expected `;` after the rule!".to_owned(),
+                    ))
+                }
+            }
+        }
+        rules.push((rule, body));
+    }
+    if rules.is_empty() {
+        return Err(Error(
+            name.span(),
+            "A grammar needs at least one rule!".to_owned(),
+        ));
+    }
+
+    let mut indices = HashMap::new();
+    let mut variants: HashMap<String, &Ident> = HashMap::new();
+    for (i, (rule, _)) in rules.iter().enumerate() {
+        if indices.insert(rule.to_string(), i).is_some() {
+            return Err(Error(
+                rule.span(),
+                format!("Rule `{}` was defined twice!", rule),
+            ));
+        }
+        if let Some(other) = variants.insert(variant(&rule.to_string()), rule) {
+            return Err(Error(
+                rule.span(),
+                format!("Rules `{}` and `{}` share a variant name!", other, rule),
+            ));
+        }
+    }
+    let mut bodies = vec![];
+    for (rule, body) in rules.iter() {
+        let mut body = body.iter().peekable();
+        let e = choice(&mut body)?;
+        if let Some(t) = body.next() {
+            return Err(Error(t.span(), "Unexpected token!".to_owned()));
+        }
+        check(&e, &indices)?;
+        bodies.push(format!(
+            "rules[{}].define({}.label({}::{}));",
+            indices[&rule.to_string()],
+            expand(&e, &indices),
+            name,
+            variant(&rule.to_string())
+        ));
+    }
+    let variants: Vec<String> = rules.iter().map(|(r, _)| variant(&r.to_string())).collect();
+    Ok(format!(
+        "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        {vis}enum {name} {{ {variants} }}
+        impl ::page::core::Identifier for {name} {{}}
+        impl {name} {{
+            /// Builds every rule of the grammar, indexed by `variant as usize`.
+            {vis}fn rules() -> [::page::rule::Rule<{name}>; {count}] {{
+                #[allow(unused_imports)]
+                use ::page::core::Parser as _;
+                let rules: [::page::rule::Rule<{name}>; {count}] =
+                    ::std::array::from_fn(|_| ::page::rule::Rule::new());
+                {bodies}
+                rules
+            }}
+            /// A parser for this rule, from a grammar built the first time
+            /// this is called on the current thread.
+            {vis}fn parser(self) -> ::std::boxed::Box<dyn ::page::core::Parser<{name}>> {{
+                ::std::thread_local! {{
+                    static RULES: [::page::rule::Rule<{name}>; {count}] = {name}::rules();
+                }}
+                RULES.with(|rules| rules[self as usize].parser())
+            }}
+        }}",
+        vis = visibility,
+        name = name,
+        variants = variants.join(", "),
+        count = rules.len(),
+        bodies = bodies.join("\n"),
+    ))
+}
+
+fn compile_error(Error(span, msg): Error) -> TokenStream {
+    let tokens: Vec<TokenTree> = vec![
+        Ident::new("compile_error", span).into(),
+        Punct::new('!', Spacing::Alone).into(),
+        Group::new(
+            Delimiter::Parenthesis,
+            TokenTree::from(Literal::string(&msg)).into(),
+        )
+        .into(),
+        Punct::new(';', Spacing::Alone).into(),
+    ];
+    tokens
+        .into_iter()
+        .map(|mut t| {
+            t.set_span(span);
+            t
+        })
+        .collect()
+}
+
+#[proc_macro]
+pub fn grammar(input: TokenStream) -> TokenStream {
+    match expand_grammar(input) {
+        Ok(code) => code.parse().unwrap(),
+        Err(e) => compile_error(e),
+    }
+}
@@ -0,0 +1,173 @@
+// Sets of characters.
+//
+// A `CharSet` is a sorted list of disjoint ranges of code points, so that
+// sets of any size, such as all letters, stay small and are searched in
+// logarithmic time. The algebra works on range boundaries: every operation
+// cuts the code points into the segments between the boundaries of both
+// sets, and keeps the segments that belong in the result.
+
+use std::sync::OnceLock;
+
+use crate::unicode;
+
+// the code points that are characters: all but the surrogates
+const CHARS: [(u32, u32); 2] = [(0, 0xD7FF), (0xE000, 0x10FFFF)];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CharSet {
+    // inclusive, neither overlapping nor adjacent
+    ranges: Vec<(u32, u32)>,
+}
+impl CharSet {
+    /// The empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The characters from `from` up to and including `to`.
+    pub fn range(from: char, to: char) -> Self {
+        assert!(from <= to, "empty range for CharSet!");
+        Self::from_ranges(vec![(from as u32, to as u32)])
+    }
+    pub fn chars(chars: impl IntoIterator<Item = char>) -> Self {
+        Self::from_ranges(chars.into_iter().map(|c| (c as u32, c as u32)).collect())
+    }
+    /// The characters for which `f` holds. This goes through every
+    /// character, so it is better built once and cloned.
+    pub fn from_predicate(f: impl Fn(char) -> bool) -> Self {
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        for c in (0..=char::MAX as u32).filter_map(char::from_u32) {
+            if !f(c) {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some((_, to)) if *to + 1 == c as u32 => *to = c as u32,
+                _ => ranges.push((c as u32, c as u32)),
+            }
+        }
+        Self::from_ranges(ranges)
+    }
+    /// `0` to `9`.
+    pub fn ascii_digit() -> Self {
+        Self::range('0', '9')
+    }
+    /// `a` to `z` and `A` to `Z`.
+    pub fn ascii_alphabetic() -> Self {
+        Self::range('a', 'z').union(&Self::range('A', 'Z'))
+    }
+    pub fn ascii_alphanumeric() -> Self {
+        Self::ascii_alphabetic().union(&Self::ascii_digit())
+    }
+    /// Unicode letters, which have the Alphabetic property, as
+    /// `char::is_alphabetic` tells.
+    pub fn alphabetic() -> Self {
+        static SET: OnceLock<CharSet> = OnceLock::new();
+        SET.get_or_init(|| Self::from_predicate(char::is_alphabetic))
+            .clone()
+    }
+    /// Unicode whitespace, as `char::is_whitespace` tells.
+    pub fn whitespace() -> Self {
+        static SET: OnceLock<CharSet> = OnceLock::new();
+        SET.get_or_init(|| Self::from_predicate(char::is_whitespace))
+            .clone()
+    }
+    /// Characters that may start an identifier, by the XID_Start property.
+    pub fn xid_start() -> Self {
+        Self::from_ranges(unicode::XID_START.to_vec())
+    }
+    /// Characters that may continue an identifier, by the XID_Continue
+    /// property.
+    pub fn xid_continue() -> Self {
+        Self::from_ranges(unicode::XID_CONTINUE.to_vec())
+    }
+    pub fn contains(&self, c: char) -> bool {
+        let c = c as u32;
+        self.ranges
+            .binary_search_by(|&(from, to)| {
+                if to < c {
+                    std::cmp::Ordering::Less
+                } else if from > c {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+    /// The ranges making up the set, in order.
+    pub fn ranges(&self) -> impl Iterator<Item = (char, char)> + '_ {
+        // ranges only ever hold characters at their ends
+        self.ranges
+            .iter()
+            .map(|&(from, to)| (char::from_u32(from).unwrap(), char::from_u32(to).unwrap()))
+    }
+    pub fn union(&self, other: &CharSet) -> CharSet {
+        self.combine(other, |a, b| a || b)
+    }
+    pub fn intersection(&self, other: &CharSet) -> CharSet {
+        self.combine(other, |a, b| a && b)
+    }
+    pub fn difference(&self, other: &CharSet) -> CharSet {
+        self.combine(other, |a, b| a && !b)
+    }
+    /// Every character not in the set.
+    pub fn negate(&self) -> CharSet {
+        self.combine(self, |a, _| !a)
+    }
+
+    fn from_ranges(mut ranges: Vec<(u32, u32)>) -> Self {
+        ranges.sort_unstable();
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(ranges.len());
+        for (from, to) in ranges {
+            match merged.last_mut() {
+                Some((_, last)) if from <= *last + 1 || (*last, from) == (0xD7FF, 0xE000) => {
+                    *last = (*last).max(to)
+                }
+                _ => merged.push((from, to)),
+            }
+        }
+        CharSet { ranges: merged }
+    }
+    // the characters for which `keep` holds of their membership in `self`
+    // and in `other`
+    fn combine(&self, other: &CharSet, keep: impl Fn(bool, bool) -> bool) -> CharSet {
+        let mut cuts: Vec<u32> = [&self.ranges[..], &other.ranges[..], &CHARS[..]]
+            .into_iter()
+            .flatten()
+            .flat_map(|&(from, to)| [from, to + 1])
+            .collect();
+        cuts.sort_unstable();
+        cuts.dedup();
+        let mut ranges = Vec::new();
+        for w in cuts.windows(2) {
+            // segments hold no boundary, so their start tells for all of them
+            let (from, to) = (w[0], w[1] - 1);
+            let Some(c) = char::from_u32(from) else {
+                continue;
+            };
+            if keep(self.contains(c), other.contains(c)) {
+                ranges.push((from, to));
+            }
+        }
+        Self::from_ranges(ranges)
+    }
+}
+impl std::fmt::Display for CharSet {
+    /// The set as a character class, such as `[0-9a-f]`, cut short after a
+    /// few ranges.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[")?;
+        for (i, (from, to)) in self.ranges().enumerate() {
+            if i == 8 {
+                return write!(f, "…]");
+            }
+            match from == to {
+                true => write!(f, "{}", from.escape_debug())?,
+                false => write!(f, "{}-{}", from.escape_debug(), to.escape_debug())?,
+            }
+        }
+        write!(f, "]")
+    }
+}
@@ -8,87 +8,110 @@
 // ParseOutput :: Succ & String
 
 use std::fmt::Debug;
+use std::ops::Deref;
 
-use crate::core::{ErrorBacktrace, Identifier, NonTerminal, ParseError, Parser, StrState};
+use crate::core::{
+    ErrorBacktrace, Identifier, Input, Logger, NonTerminal, ParseError, ParseResult, Parser, Skip,
+    Skipped, Span, State,
+};
+use crate::memo::apply;
 
-pub struct ParserCatenate<T> {
-    pub(crate) recipe: Box<dyn Parser<T>>,
+// Runs `p` as one alternative of a choice: in a scope of its own for cuts,
+// as a point the parse may backtrack to. The state returned with an error
+// tells whether a cut committed it.
+#[allow(clippy::result_large_err)]
+fn alternative<'a, T: Identifier, S: ?Sized + Input>(
+    p: &'a dyn Parser<T, S>,
+    input: State<'a, S>,
+) -> ParseResult<'a, T, S> {
+    input.backtrack(true, || apply(p, input.uncut()))
 }
-impl<T: Identifier> Debug for ParserCatenate<T> {
+
+pub struct ParserCatenate<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserCatenate<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "catenation of {:?}", self.recipe)
     }
 }
-impl<T: Identifier> Parser<T> for ParserCatenate<T> {
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserCatenate<T, S> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
-        match self.recipe.run(input) {
-            Ok((_, s)) => Ok((NonTerminal::Leaf(&input.string[input.head..s.head]), s)),
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        match apply(self.recipe.as_ref(), input) {
+            Ok((_, s)) => Ok((
+                NonTerminal::Leaf(
+                    input.string.slice(input.head, s.head),
+                    Span::between(&input, &s),
+                ),
+                s,
+            )),
             e => e,
         }
     }
-    fn catenate(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn catenate(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
 }
 
-pub struct ParserMsg<T> {
-    pub(crate) recipe: Box<dyn Parser<T>>,
+pub struct ParserMsg<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
     pub(crate) msg: &'static str,
 }
-impl<T: Identifier> Debug for ParserMsg<T> {
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserMsg<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self.recipe)
     }
 }
-impl<T: Identifier> Parser<T> for ParserMsg<T> {
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserMsg<T, S> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
-        match self.recipe.run(input) {
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        match apply(self.recipe.as_ref(), input) {
             Err((mut e, s)) => {
                 if e.msg.is_none() {
                     e.msg = Some(self.msg);
                 }
-                return Err((e, s));
+                Err((e, s))
             }
             o => o,
         }
     }
-    fn msg(mut self: Box<Self>, msg: &'static str) -> Box<dyn Parser<T>> {
+    fn msg(mut self: Box<Self>, msg: &'static str) -> Box<dyn Parser<T, S>> {
         self.msg = msg;
         self
     }
 }
 
-pub struct ParserLabeled<T> {
-    pub(crate) recipe: Box<dyn Parser<T>>,
+pub struct ParserLabeled<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
     pub(crate) label: T,
 }
-impl<T: Identifier> Debug for ParserLabeled<T> {
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserLabeled<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self.recipe)
     }
 }
-impl<T: Identifier> Parser<T> for ParserLabeled<T> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserLabeled<T, S> {
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
-        match self.recipe.run(input) {
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        match apply(self.recipe.as_ref(), input) {
             Ok((n, s)) => Ok((
                 NonTerminal::Node {
                     identifier: self.label,
                     children: Box::new(n),
+                    span: Span::between(&input, &s),
                 },
                 s,
             )),
@@ -102,19 +125,19 @@ impl<T: Identifier> Parser<T> for ParserLabeled<T> {
             }
         }
     }
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
-    fn label(mut self: Box<Self>, ident: T) -> Box<dyn Parser<T>> {
+    fn label(mut self: Box<Self>, ident: T) -> Box<dyn Parser<T, S>> {
         self.label = ident;
         self
     }
 }
 
-pub struct ParserSeq<T> {
-    pub(crate) recipe: Vec<Box<dyn Parser<T>>>,
+pub struct ParserSeq<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Vec<Box<dyn Parser<T, S>>>,
 }
-impl<T: Identifier> Debug for ParserSeq<T> {
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserSeq<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Sequence of")?;
         for p in self.recipe.iter() {
@@ -123,39 +146,54 @@ impl<T: Identifier> Debug for ParserSeq<T> {
         Ok(())
     }
 }
-impl<T: Identifier> Parser<T> for ParserSeq<T> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserSeq<T, S> {
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
         let mut children = Vec::new();
         let mut head = input;
-        for p in self.recipe.iter() {
-            match p.run(head) {
+        for (i, p) in self.recipe.iter().enumerate() {
+            if i > 0 {
+                head = match head.skip_trivia(self, &mut children) {
+                    Ok(s) => s,
+                    Err(e) => return Err((e, State { cut: true, ..input })),
+                };
+            }
+            match apply(p.as_ref(), head) {
                 Ok((n, s)) => {
                     children.push(n);
                     head = s;
                 }
-                Err((e, _)) => {
-                    return Err((e, input));
+                Err((e, s)) => {
+                    return Err((
+                        e,
+                        State {
+                            cut: s.cut,
+                            ..input
+                        },
+                    ));
                 }
             }
         }
-        return Ok((NonTerminal::Congregate(children), head));
+        Ok((
+            NonTerminal::Congregate(children, Span::between(&input, &head)),
+            head,
+        ))
     }
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
-    fn seq(mut self: Box<Self>, p: Box<dyn Parser<T>>) -> Box<dyn Parser<T>> {
+    fn seq(mut self: Box<Self>, p: Box<dyn Parser<T, S>>) -> Box<dyn Parser<T, S>> {
         self.recipe.push(p);
         self
     }
 }
 
-pub struct ParserChoice<T> {
-    pub(crate) recipe: Vec<Box<dyn Parser<T>>>,
+pub struct ParserChoice<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Vec<Box<dyn Parser<T, S>>>,
 }
-impl<T: Identifier> Debug for ParserChoice<T> {
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserChoice<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Choice between")?;
         for p in self.recipe.iter() {
@@ -164,235 +202,763 @@ impl<T: Identifier> Debug for ParserChoice<T> {
         Ok(())
     }
 }
-impl<T: Identifier> Parser<T> for ParserChoice<T> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserChoice<T, S> {
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        // the error of the alternative that got furthest, if any got past
+        // the start
+        let mut furthest: Option<ParseError<'a, T, S>> = None;
         for p in self.recipe.iter() {
-            match p.run(input) {
-                Err(_) => {}
-                e => {
-                    return e;
+            match alternative(p.as_ref(), input) {
+                Ok((n, s)) => {
+                    return Ok((
+                        n,
+                        State {
+                            cut: input.cut,
+                            ..s
+                        },
+                    ))
+                }
+                Err((e, s)) if s.cut => return Err((e, State { cut: true, ..input })),
+                Err((e, _)) => {
+                    let at = (e.location.1, e.location.2);
+                    let best = match &furthest {
+                        Some(f) => (f.location.1, f.location.2),
+                        None => (input.line, input.column),
+                    };
+                    if at > best {
+                        furthest = Some(e);
+                    }
                 }
             }
         }
-        return Err((
+        if let Some(e) = furthest {
+            return Err((e, input));
+        }
+        Err((
             ParseError {
                 location: (input.line_of(), input.line, input.column),
+                head: input.head,
                 expected: self,
                 backtrace: ErrorBacktrace::Empty,
                 msg: None,
+                ctx: input.ctx,
             },
             input,
-        ));
+        ))
     }
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
-    fn or(mut self: Box<Self>, p: Box<dyn Parser<T>>) -> Box<dyn Parser<T>> {
+    fn or(mut self: Box<Self>, p: Box<dyn Parser<T, S>>) -> Box<dyn Parser<T, S>> {
         self.recipe.push(p);
         self
     }
 }
 
-pub struct ParserPlus<T> {
-    pub(crate) recipe: Box<dyn Parser<T>>,
+pub struct ParserPlus<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
 }
-impl<T: Identifier> Debug for ParserPlus<T> {
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserPlus<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?} at least once", self.recipe)
     }
 }
-impl<T: Identifier> Parser<T> for ParserPlus<T> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserPlus<T, S> {
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
-        let first_res = self.recipe.run(input);
-        if let Ok((n, mut head)) = first_res {
-            let mut children = vec![n];
-            while let Ok((n, s)) = self.recipe.run(head) {
-                children.push(n);
-                head = s;
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        let mut children = Vec::new();
+        let mut head = input;
+        loop {
+            let mut trivia = Vec::new();
+            let next = match children.is_empty() {
+                true => head,
+                false => match head.skip_trivia(self, &mut trivia) {
+                    Ok(s) => s,
+                    Err(e) => return Err((e, State { cut: true, ..input })),
+                },
+            };
+            match alternative(self.recipe.as_ref(), next) {
+                Ok((n, s)) => {
+                    children.append(&mut trivia);
+                    children.push(n);
+                    head = State {
+                        cut: input.cut,
+                        ..s
+                    };
+                }
+                Err((e, s)) if s.cut || children.is_empty() => {
+                    return Err((
+                        e,
+                        State {
+                            cut: s.cut || input.cut,
+                            ..input
+                        },
+                    ))
+                }
+                Err(_) => break,
             }
-            return Ok((NonTerminal::Congregate(children), head));
-        } else {
-            return first_res;
         }
+        Ok((
+            NonTerminal::Congregate(children, Span::between(&input, &head)),
+            head,
+        ))
     }
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
-    fn atleast_once(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn atleast_once(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
 }
 
-pub struct ParserStar<T> {
-    pub(crate) recipe: Box<dyn Parser<T>>,
+pub struct ParserStar<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
 }
-impl<T: Identifier> Debug for ParserStar<T> {
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserStar<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?} as often as possible", self.recipe)
     }
 }
-impl<T: Identifier> Parser<T> for ParserStar<T> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserStar<T, S> {
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
         let mut children = Vec::new();
         let mut head = input;
-        while let Ok((n, s)) = self.recipe.run(head) {
-            children.push(n);
-            head = s;
+        loop {
+            let mut trivia = Vec::new();
+            let next = match children.is_empty() {
+                true => head,
+                false => match head.skip_trivia(self, &mut trivia) {
+                    Ok(s) => s,
+                    Err(e) => return Err((e, State { cut: true, ..input })),
+                },
+            };
+            match alternative(self.recipe.as_ref(), next) {
+                Ok((n, s)) => {
+                    children.append(&mut trivia);
+                    children.push(n);
+                    head = State {
+                        cut: input.cut,
+                        ..s
+                    };
+                }
+                Err((e, s)) if s.cut => return Err((e, State { cut: true, ..input })),
+                Err(_) => break,
+            }
         }
         if children.is_empty() {
-            return Ok((NonTerminal::Empty, head));
+            return Ok((NonTerminal::Empty(Span::at(&head)), head));
         }
-        return Ok((NonTerminal::Congregate(children), head));
+        Ok((
+            NonTerminal::Congregate(children, Span::between(&input, &head)),
+            head,
+        ))
     }
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
-    fn multiple(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn multiple(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
 }
 
-pub struct ParserMaybe<T> {
-    pub(crate) recipe: Box<dyn Parser<T>>,
+pub struct ParserMaybe<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
 }
-impl<T: Identifier> Debug for ParserMaybe<T> {
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserMaybe<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?} or nothing", self.recipe)
     }
 }
-impl<T: Identifier> Parser<T> for ParserMaybe<T> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserMaybe<T, S> {
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
-        match self.recipe.run(input) {
-            Err(_) => Ok((NonTerminal::Empty, input)),
-            o => o,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        match alternative(self.recipe.as_ref(), input) {
+            Ok((n, s)) => Ok((
+                n,
+                State {
+                    cut: input.cut,
+                    ..s
+                },
+            )),
+            Err((e, s)) if s.cut => Err((e, State { cut: true, ..input })),
+            Err(_) => Ok((NonTerminal::Empty(Span::at(&input)), input)),
         }
     }
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
-    fn maybe(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn maybe(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
 }
 
-pub struct ParserEnsure<T> {
-    pub(crate) recipe: Box<dyn Parser<T>>,
+pub struct ParserEnsure<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
 }
-impl<T: Identifier> Debug for ParserEnsure<T> {
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserEnsure<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Ensure {:?}", self.recipe)
     }
 }
-impl<T: Identifier> Parser<T> for ParserEnsure<T> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserEnsure<T, S> {
+    #[allow(clippy::result_large_err)]
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
-        match self.recipe.run(input) {
-            Ok(_) => Ok((NonTerminal::Empty, input)),
-            e => e,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        match input.backtrack(false, || apply(self.recipe.as_ref(), input.uncut())) {
+            Ok(_) => Ok((NonTerminal::Empty(Span::at(&input)), input)),
+            Err((e, _)) => Err((e, input)),
         }
     }
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
-    fn ensure(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn ensure(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
 }
 
-pub struct ParserAvoid<T> {
-    pub(crate) recipe: Box<dyn Parser<T>>,
+pub struct ParserAvoid<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
 }
-impl<T: Identifier> Debug for ParserAvoid<T> {
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserAvoid<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Avoid {:?}", self.recipe)
     }
 }
-impl<T: Identifier> Parser<T> for ParserAvoid<T> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserAvoid<T, S> {
+    #[allow(clippy::result_large_err)]
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
-        match self.recipe.run(input) {
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        // what the lookahead expected is no reason for failing here
+        let outer = input.ctx.map(|ctx| ctx.take_furthest());
+        let res = input.backtrack(false, || apply(self.recipe.as_ref(), input.uncut()));
+        if let (Some(ctx), Some(outer)) = (input.ctx, outer) {
+            ctx.take_furthest();
+            ctx.merge_furthest(outer);
+        }
+        match res {
             Ok(_) => Err((
                 ParseError {
                     location: (input.line_of(), input.line, input.column),
+                    head: input.head,
                     expected: self.recipe.as_ref(),
                     backtrace: ErrorBacktrace::Empty,
                     msg: None,
+                    ctx: input.ctx,
                 },
                 input,
             )),
-            Err(_) => Ok((NonTerminal::Empty, input)),
+            Err(_) => Ok((NonTerminal::Empty(Span::at(&input)), input)),
         }
     }
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
-    fn avoid(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn avoid(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
 }
 
-pub struct ParserLog<T: Identifier> {
-    pub(crate) recipe: Box<dyn Parser<T>>,
-    pub(crate) logger: Box<
-        dyn Fn(
-            &Result<(NonTerminal<'_, T>, StrState<'_>), (ParseError<'_, T>, StrState<'_>)>,
-        ) -> (),
-    >,
-}
-impl<T: Identifier> Debug for ParserLog<T> {
+pub struct ParserLog<T: Identifier, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
+    pub(crate) logger: Logger<T, S>,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserLog<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self.recipe)
     }
 }
-impl<T: Identifier> Parser<T> for ParserLog<T> {
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserLog<T, S> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
-        let res = self.recipe.run(input);
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        let res = apply(self.recipe.as_ref(), input);
         (self.logger)(&res);
         res
     }
 }
 
-pub struct ParserIgnoreRes<T: Identifier> {
-    pub(crate) recipe: Box<dyn Parser<T>>,
+pub struct ParserIgnoreRes<T: Identifier, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
 }
-impl<T: Identifier> Debug for ParserIgnoreRes<T> {
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserIgnoreRes<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self.recipe)
     }
 }
-impl<T: Identifier> Parser<T> for ParserIgnoreRes<T> {
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserIgnoreRes<T, S> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         self
     }
     fn run<'a>(
         &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
-        match self.recipe.run(input) {
-            Ok((_, s)) => Ok((NonTerminal::Empty, s)),
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        match apply(self.recipe.as_ref(), input) {
+            Ok((_, s)) => Ok((NonTerminal::Empty(Span::between(&input, &s)), s)),
             e => e,
         }
     }
 }
+
+pub struct ParserMemo<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserMemo<T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.recipe)
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserMemo<T, S> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        match input.ctx {
+            Some(ctx) => ctx.memo.cached(self.recipe.as_ref(), input),
+            None => self.recipe.run(input),
+        }
+    }
+    fn memo(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+}
+
+pub struct ParserRecover<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
+    pub(crate) sync: Box<dyn Parser<T, S>>,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserRecover<T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} recovering until\n- {:?}", self.recipe, self.sync)
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserRecover<T, S> {
+    #[allow(clippy::result_large_err)]
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        let e = match input.backtrack(false, || apply(self.recipe.as_ref(), input)) {
+            Err((e, _)) => e,
+            o => return o,
+        };
+        let mut head = input;
+        let end = loop {
+            if let Ok((_, s)) = apply(self.sync.as_ref(), head) {
+                break s;
+            }
+            match head.deref().first() {
+                Some((_, l)) => head = head.advance(l),
+                None => break head,
+            }
+        };
+        if end.head == input.head {
+            return Err((e, input));
+        }
+        Ok((
+            NonTerminal::Error {
+                error: Box::new(e),
+                skipped: input.string.slice(input.head, end.head),
+                span: Span::between(&input, &end),
+            },
+            State {
+                cut: input.cut,
+                ..end
+            },
+        ))
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+}
+
+pub struct ParserName<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
+    pub(crate) name: &'static str,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserName<T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:?})", self.name, self.recipe)
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserName<T, S> {
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        let Some(ctx) = input.ctx else {
+            return apply(self.recipe.as_ref(), input);
+        };
+        let outer = ctx.take_furthest();
+        let res = apply(self.recipe.as_ref(), input);
+        let inner = ctx.take_furthest();
+        ctx.merge_furthest(outer);
+        match inner {
+            Some(f) if res.is_ok() || f.head > input.head => ctx.merge_furthest(Some(f)),
+            _ if res.is_err() => input.expected(|| self.name.to_owned()),
+            _ => {}
+        }
+        res
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+    fn name(mut self: Box<Self>, name: &'static str) -> Box<dyn Parser<T, S>> {
+        self.name = name;
+        self
+    }
+}
+
+pub struct ParserCut<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserCut<T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}, then commit", self.recipe)
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserCut<T, S> {
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        let (n, s) = apply(self.recipe.as_ref(), input)?;
+        s.commit();
+        Ok((n, State { cut: true, ..s }))
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+    fn cut(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+}
+
+pub struct ParserSkipping<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
+    pub(crate) trivia: Box<dyn Parser<T, S>>,
+    pub(crate) keep: bool,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserSkipping<T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}, skipping {:?}", self.recipe, self.trivia)
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserSkipping<T, S> {
+    #[allow(clippy::result_large_err)]
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        let outer = input.trivia;
+        let restore = |s: State<'a, S>| State { trivia: outer, ..s };
+        let mut children = Vec::new();
+        let skipping = State {
+            trivia: Some(self),
+            ..input
+        };
+        let fail = |e| Err((e, State { cut: true, ..input }));
+        let head = match skipping.skip_trivia(self, &mut children) {
+            Ok(s) => s,
+            Err(e) => return fail(e),
+        };
+        let (n, s) = match apply(self.recipe.as_ref(), head) {
+            Ok(r) => r,
+            Err((e, s)) => return Err((e, restore(s))),
+        };
+        children.push(n);
+        let end = match s.skip_trivia(self, &mut children) {
+            Ok(s) => s,
+            Err(e) => return fail(e),
+        };
+        match children.len() {
+            1 => Ok((children.pop().unwrap(), restore(end))),
+            _ => Ok((
+                NonTerminal::Congregate(children, Span::between(&input, &end)),
+                restore(end),
+            )),
+        }
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Skip<S> for ParserSkipping<T, S> {
+    fn skip<'a>(&'a self, input: State<'a, S>) -> Skipped<'a, S> {
+        skip(self.trivia.as_ref(), self.keep, input)
+    }
+}
+
+/// Runs `trivia` at `input` for `Skip`, telling whether to `keep` what it
+/// skipped.
+pub(crate) fn skip<'a, T: Identifier, S: ?Sized + Input>(
+    trivia: &'a dyn Parser<T, S>,
+    keep: bool,
+    input: State<'a, S>,
+) -> Skipped<'a, S> {
+    // what trivia expected is no news where the grammar expects more
+    let furthest = input.ctx.and_then(|ctx| ctx.take_furthest());
+    let res = apply(
+        trivia,
+        State {
+            trivia: None,
+            ..input.uncut()
+        },
+    );
+    if let Some(ctx) = input.ctx {
+        ctx.take_furthest();
+        ctx.merge_furthest(furthest);
+    }
+    match res {
+        Ok((_, s)) => Ok((
+            State {
+                cut: input.cut,
+                trivia: input.trivia,
+                ..s
+            },
+            keep,
+        )),
+        Err((e, s)) if s.cut => Err((
+            State {
+                head: e.head,
+                line: e.location.1,
+                column: e.location.2,
+                ..input
+            },
+            e.msg,
+        )),
+        Err(_) => Ok((input, false)),
+    }
+}
+
+pub struct ParserAtomic<T, S: ?Sized + Input = str> {
+    pub(crate) recipe: Box<dyn Parser<T, S>>,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserAtomic<T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} as a whole", self.recipe)
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserAtomic<T, S> {
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        let inside = State {
+            trivia: None,
+            ..input
+        };
+        match apply(self.recipe.as_ref(), inside) {
+            Ok((n, s)) => Ok((
+                n,
+                State {
+                    trivia: input.trivia,
+                    ..s
+                },
+            )),
+            Err((e, s)) => Err((
+                e,
+                State {
+                    trivia: input.trivia,
+                    ..s
+                },
+            )),
+        }
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+    fn atomic(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+}
+
+/// Whether a separated list may end with a separator, see `Parser::sep_by`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trailing {
+    /// `a, b` and `a, b,`.
+    Allow,
+    /// Only `a, b,`: every item is followed by a separator.
+    Require,
+    /// Only `a, b`: a separator after the last item is left to what follows.
+    Forbid,
+}
+
+pub struct ParserSepBy<T, S: ?Sized + Input = str> {
+    pub(crate) item: Box<dyn Parser<T, S>>,
+    pub(crate) sep: Box<dyn Parser<T, S>>,
+    pub(crate) trailing: Trailing,
+    pub(crate) least: usize,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserSepBy<T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "at least {} of {:?} separated by {:?}",
+            self.least, self.item, self.sep
+        )
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserSepBy<T, S> {
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        // items and separators alternate, each with the trivia before it and
+        // the state after it
+        let mut steps = Vec::new();
+        let mut head = input;
+        let failure = loop {
+            let mut nodes = Vec::new();
+            let next = match steps.is_empty() {
+                true => head,
+                false => match head.skip_trivia(self, &mut nodes) {
+                    Ok(s) => s,
+                    Err(e) => return Err((e, State { cut: true, ..input })),
+                },
+            };
+            let p = match steps.len() % 2 {
+                0 => self.item.as_ref(),
+                _ => self.sep.as_ref(),
+            };
+            match alternative(p, next) {
+                Ok((n, s)) => {
+                    nodes.push(n);
+                    head = State {
+                        cut: input.cut,
+                        ..s
+                    };
+                    steps.push((nodes, head));
+                }
+                Err((e, s)) if s.cut => return Err((e, State { cut: true, ..input })),
+                Err((e, _)) => break e,
+            }
+        };
+        let ends_with_sep = steps.len() % 2 == 0;
+        match self.trailing {
+            Trailing::Forbid if ends_with_sep && !steps.is_empty() => drop(steps.pop()),
+            Trailing::Require if !ends_with_sep => drop(steps.pop()),
+            _ => {}
+        }
+        if steps.len().div_ceil(2) < self.least {
+            return Err((failure, input));
+        }
+        let Some(&(_, end)) = steps.last() else {
+            return Ok((NonTerminal::Empty(Span::at(&input)), input));
+        };
+        Ok((
+            NonTerminal::Congregate(
+                steps.into_iter().flat_map(|(nodes, _)| nodes).collect(),
+                Span::between(&input, &end),
+            ),
+            end,
+        ))
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+}
+
+/// Parses `inner` between `open` and `close`, into the trees of the three
+/// side by side, with those of a `Congregate` from `inner` spliced in. If
+/// `close` is missing, the error points back at where `open` matched.
+pub fn delimited<T: Identifier, S: ?Sized + Input>(
+    open: Box<dyn Parser<T, S>>,
+    inner: Box<dyn Parser<T, S>>,
+    close: Box<dyn Parser<T, S>>,
+) -> Box<dyn Parser<T, S>> {
+    Box::new(ParserDelimited { open, inner, close })
+}
+pub struct ParserDelimited<T, S: ?Sized + Input = str> {
+    pub(crate) open: Box<dyn Parser<T, S>>,
+    pub(crate) inner: Box<dyn Parser<T, S>>,
+    pub(crate) close: Box<dyn Parser<T, S>>,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserDelimited<T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} between\n- {:?}\n- {:?}",
+            self.inner, self.open, self.close
+        )
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserDelimited<T, S> {
+    #[allow(clippy::result_large_err)]
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        let fail = |e, s: State<'a, S>| {
+            Err((
+                e,
+                State {
+                    cut: s.cut || input.cut,
+                    ..input
+                },
+            ))
+        };
+        let mut children = Vec::new();
+        let (n, s) = match apply(self.open.as_ref(), input) {
+            Ok(r) => r,
+            Err((e, s)) => return fail(e, s),
+        };
+        children.push(n);
+        let mut head = s;
+        for (p, closing) in [(&self.inner, false), (&self.close, true)] {
+            head = match head.skip_trivia(self, &mut children) {
+                Ok(s) => s,
+                Err(e) => return fail(e, State { cut: true, ..input }),
+            };
+            let (n, s) = match apply(p.as_ref(), head) {
+                Ok(r) => r,
+                Err((e, s)) if s.cut || !closing => return fail(e, s),
+                Err((e, s)) => {
+                    let e = ParseError {
+                        location: (input.line_of(), input.line, input.column),
+                        head: input.head,
+                        expected: self.close.as_ref(),
+                        msg: Some("Unclosed delimiter!"),
+                        ..e
+                    };
+                    return fail(e, s);
+                }
+            };
+            match n {
+                NonTerminal::Congregate(v, _) => children.extend(v),
+                NonTerminal::Empty(_) => {}
+                n => children.push(n),
+            }
+            head = s;
+        }
+        Ok((
+            NonTerminal::Congregate(children, Span::between(&input, &head)),
+            head,
+        ))
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+}
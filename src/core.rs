@@ -1,13 +1,146 @@
-use std::ops::Deref;
+use std::cell::RefCell;
+use std::ops::{Deref, Range};
 
 use crate::combinators::{
-    ParserAvoid, ParserCatenate, ParserChoice, ParserEnsure, ParserIgnoreRes, ParserLabeled,
-    ParserLog, ParserMaybe, ParserMsg, ParserPlus, ParserSeq, ParserStar,
+    ParserAtomic, ParserAvoid, ParserCatenate, ParserChoice, ParserCut, ParserEnsure,
+    ParserIgnoreRes, ParserLabeled, ParserLog, ParserMaybe, ParserMemo, ParserMsg, ParserName,
+    ParserPlus, ParserRecover, ParserSepBy, ParserSeq, ParserSkipping, ParserStar, Trailing,
 };
+use crate::diagnostic::display;
+use crate::indent::Level;
+use crate::memo::{Memo, MemoStats, Shift};
 
 pub trait Identifier: Copy + 'static {}
 
-#[derive(Debug)]
+/// Input a parser can run over, implemented for text (`str`), bytes (`[u8]`)
+/// and slices of tokens (`[K]` for any `K: Token`).
+///
+/// Positions are offsets into the input: bytes for text and bytes, items for
+/// tokens.
+pub trait Input: std::fmt::Debug + PartialEq + 'static {
+    type Item: Copy + std::fmt::Debug + PartialEq;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The input from position `from` up to `to`.
+    fn slice(&self, from: usize, to: usize) -> &Self;
+    /// The first item and the number of positions it takes up.
+    fn first(&self) -> Option<(Self::Item, usize)>;
+    fn starts_with(&self, prefix: &Self) -> bool;
+    /// Position at which `part`, a slice of `self`, starts.
+    fn offset(&self, part: &Self) -> usize;
+    /// Line and column reached by reading through `self` from `line` and
+    /// `column`.
+    fn locate(&self, line: usize, column: usize) -> (usize, usize);
+    /// The text of line `line`, used to point at errors.
+    fn line_of(&self, line: usize) -> &str;
+}
+impl Input for str {
+    type Item = char;
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn slice(&self, from: usize, to: usize) -> &Self {
+        &self[from..to]
+    }
+    fn first(&self) -> Option<(char, usize)> {
+        self.chars().next().map(|c| (c, c.len_utf8()))
+    }
+    fn starts_with(&self, prefix: &Self) -> bool {
+        self.starts_with(prefix)
+    }
+    fn offset(&self, part: &Self) -> usize {
+        part.as_ptr() as usize - self.as_ptr() as usize
+    }
+    fn locate(&self, mut line: usize, mut column: usize) -> (usize, usize) {
+        for c in self.chars() {
+            if c == '\n' {
+                column = 0;
+                line += 1;
+            } else {
+                column += 1;
+            }
+        }
+        (line, column)
+    }
+    fn line_of(&self, line: usize) -> &str {
+        self.lines().nth(line).unwrap_or("")
+    }
+}
+impl Input for [u8] {
+    type Item = u8;
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn slice(&self, from: usize, to: usize) -> &Self {
+        &self[from..to]
+    }
+    fn first(&self) -> Option<(u8, usize)> {
+        self.first().map(|b| (*b, 1))
+    }
+    fn starts_with(&self, prefix: &Self) -> bool {
+        self.starts_with(prefix)
+    }
+    fn offset(&self, part: &Self) -> usize {
+        part.as_ptr() as usize - self.as_ptr() as usize
+    }
+    fn locate(&self, mut line: usize, mut column: usize) -> (usize, usize) {
+        for b in self.iter() {
+            if *b == b'\n' {
+                column = 0;
+                line += 1;
+            } else {
+                column += 1;
+            }
+        }
+        (line, column)
+    }
+    fn line_of(&self, line: usize) -> &str {
+        self.split(|b| *b == b'\n')
+            .nth(line)
+            .and_then(|l| std::str::from_utf8(l).ok())
+            .unwrap_or("")
+    }
+}
+
+/// Items of a token slice, as produced by a separate lexer.
+pub trait Token: Copy + std::fmt::Debug + PartialEq + 'static {}
+impl Token for char {}
+impl<K: Token> Input for [K] {
+    type Item = K;
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn slice(&self, from: usize, to: usize) -> &Self {
+        &self[from..to]
+    }
+    fn first(&self) -> Option<(K, usize)> {
+        self.first().map(|k| (*k, 1))
+    }
+    fn starts_with(&self, prefix: &Self) -> bool {
+        self.starts_with(prefix)
+    }
+    fn offset(&self, part: &Self) -> usize {
+        (part.as_ptr() as usize - self.as_ptr() as usize) / std::mem::size_of::<K>().max(1)
+    }
+    // tokens all sit on one line, one column each
+    fn locate(&self, line: usize, column: usize) -> (usize, usize) {
+        (line, column + self.len())
+    }
+    fn line_of(&self, _: usize) -> &str {
+        ""
+    }
+}
+
+pub type ParseResult<'a, T, S = str> =
+    Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)>;
+pub type Logger<T, S = str> = Box<dyn Fn(&ParseResult<'_, T, S>)>;
+// the state after skipped trivia and whether to keep it, or where and why
+// the trivia failed for good
+pub(crate) type Skipped<'a, S> = Result<(State<'a, S>, bool), (State<'a, S>, Option<&'static str>)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ErrorBacktrace<T: Identifier> {
     Node { identifier: T, next: Box<Self> },
     Empty,
@@ -31,17 +164,55 @@ impl<T: Identifier + std::fmt::Debug> ErrorBacktrace<T> {
 }
 
 fn point(s: &str, i: usize) -> String {
-    format!("{s}\n{}^{}", " ".repeat(i), "~".repeat(s.len() - i - 1))
+    let (text, columns) = display(s);
+    let end = columns[columns.len() - 1];
+    let at = columns.get(i).copied().unwrap_or(end);
+    format!(
+        "{text}\n{}^{}",
+        " ".repeat(at),
+        "~".repeat(end.saturating_sub(at + 1))
+    )
 }
 
 #[derive(Debug)]
-pub struct ParseError<'a, T: Identifier> {
+pub struct ParseError<'a, T: Identifier, S: ?Sized + Input = str> {
     pub(crate) location: (&'a str, usize, usize),
-    pub(crate) expected: &'a dyn Parser<T>,
+    pub(crate) head: usize,
+    pub(crate) expected: &'a dyn Parser<T, S>,
     pub(crate) backtrace: ErrorBacktrace<T>,
     pub(crate) msg: Option<&'static str>,
+    pub(crate) ctx: Option<&'a Context>,
+}
+impl<T: Identifier, S: ?Sized + Input> Clone for ParseError<'_, T, S> {
+    fn clone(&self) -> Self {
+        ParseError {
+            location: self.location,
+            head: self.head,
+            expected: self.expected,
+            backtrace: self.backtrace.clone(),
+            msg: self.msg,
+            ctx: self.ctx,
+        }
+    }
 }
-impl<T: Identifier> std::fmt::Display for ParseError<'_, T> {
+impl<T: Identifier, S: ?Sized + Input> ParseError<'_, T, S> {
+    /// What the run that failed expected at the furthest position it
+    /// reached, when it had a context to keep track of it.
+    pub fn expected(&self) -> Option<Expected> {
+        self.ctx.and_then(|ctx| ctx.expected())
+    }
+}
+// errors are equal when they point at the same place for the same reason,
+// whichever parser reported them
+impl<T: Identifier + PartialEq, S: ?Sized + Input> PartialEq for ParseError<'_, T, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.location == other.location
+            && self.backtrace == other.backtrace
+            && self.msg == other.msg
+    }
+}
+impl<T: Identifier + Eq, S: ?Sized + Input> Eq for ParseError<'_, T, S> {}
+impl<T: Identifier, S: ?Sized + Input> std::fmt::Display for ParseError<'_, T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
@@ -50,16 +221,104 @@ impl<T: Identifier> std::fmt::Display for ParseError<'_, T> {
             self.location.2,
             point(self.location.0, self.location.2)
         )?;
-        match self.msg {
-            Some(s) => write!(f, "\nNote:\n{}", s),
-            None => write!(
+        match (self.msg, self.expected()) {
+            (Some(s), Some(x)) => write!(f, "\nNote:\n{}\n{}", s, x),
+            (Some(s), None) => write!(f, "\nNote:\n{}", s),
+            (None, Some(x)) => write!(f, "\nNote:\n{}", x),
+            (None, None) => write!(
                 f,
                 "\nNote:\nSee this error's info print for more information!"
             ),
         }
     }
 }
-impl<T: Identifier + std::fmt::Debug> ParseError<'_, T> {
+
+/// A `ParseError` that owns everything it reports, so that it can outlive
+/// the input and the grammar, be sent to other threads, and be returned as
+/// a `std::error::Error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedParseError<T: Identifier> {
+    /// Position of the error in the input.
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    /// The text of the line the error is on.
+    pub line_text: String,
+    /// What the run expected at the furthest position it reached, when it
+    /// had a context, or else the parser that failed.
+    pub expected: String,
+    pub backtrace: ErrorBacktrace<T>,
+    pub msg: Option<&'static str>,
+}
+impl<T: Identifier, S: ?Sized + Input> ParseError<'_, T, S> {
+    pub fn owned(&self) -> OwnedParseError<T> {
+        OwnedParseError {
+            offset: self.head,
+            line: self.location.1,
+            column: self.location.2,
+            line_text: self.location.0.to_owned(),
+            expected: match self.expected() {
+                Some(x) => x.to_string(),
+                None => format!("{:?}", self.expected),
+            },
+            backtrace: self.backtrace.clone(),
+            msg: self.msg,
+        }
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> From<ParseError<'_, T, S>> for OwnedParseError<T> {
+    fn from(e: ParseError<'_, T, S>) -> Self {
+        e.owned()
+    }
+}
+impl<T: Identifier> std::fmt::Display for OwnedParseError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}:{}]\tParsing error occured in string:\n{}\nNote:\n",
+            self.line,
+            self.column,
+            point(&self.line_text, self.column)
+        )?;
+        match self.msg {
+            Some(s) => write!(f, "{}\n{}", s, self.expected),
+            None => write!(f, "{}", self.expected),
+        }
+    }
+}
+impl<T: Identifier + std::fmt::Debug> std::error::Error for OwnedParseError<T> {}
+
+/// Everything the parsers of a run expected at the furthest position where
+/// any of them failed: literals in backticks, and the names given with
+/// `name()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expected {
+    pub line: usize,
+    pub column: usize,
+    pub items: Vec<String>,
+}
+impl Expected {
+    // what was expected, without where
+    pub(crate) fn describe(&self) -> String {
+        match self.items.len() {
+            1 => format!("expected {}", self.items[0]),
+            _ => format!("expected one of {}", self.items.join(", ")),
+        }
+    }
+}
+impl std::fmt::Display for Expected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}:{}", self.describe(), self.line, self.column)
+    }
+}
+
+// an `Expected` along with its position in the input
+#[derive(Clone)]
+pub(crate) struct Furthest {
+    pub(crate) head: usize,
+    pub(crate) expected: Expected,
+}
+impl<T: Identifier + std::fmt::Debug, S: ?Sized + Input> ParseError<'_, T, S> {
     pub fn info(&self) -> String {
         format!(
             "[{}:{}]\tParsing error occured in string:\n{}\nIn parser:\n{:?}\nfollowing this backtrace:\n{}",
@@ -72,170 +331,635 @@ impl<T: Identifier + std::fmt::Debug> ParseError<'_, T> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum NonTerminal<'a, T: Identifier> {
-    Node { identifier: T, children: Box<Self> },
-    Congregate(Vec<Self>),
-    // this imposes that StrState lives as long
+/// Part of the input a node was parsed from: positions `start` up to `end`,
+/// with the line and column of both.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+impl Span {
+    /// The span from `from` up to `to`.
+    pub(crate) fn between<S: ?Sized + Input>(from: &State<'_, S>, to: &State<'_, S>) -> Self {
+        Span {
+            start: from.head,
+            end: to.head,
+            line: from.line,
+            column: from.column,
+            end_line: to.line,
+            end_column: to.column,
+        }
+    }
+    /// The empty span at `s`.
+    pub(crate) fn at<S: ?Sized + Input>(s: &State<'_, S>) -> Self {
+        Self::between(s, s)
+    }
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+    /// The smallest span covering both `self` and `other`.
+    pub fn merge(self, other: Span) -> Span {
+        let first = if other.start < self.start {
+            other
+        } else {
+            self
+        };
+        let last = if other.end > self.end { other } else { self };
+        Span {
+            start: first.start,
+            line: first.line,
+            column: first.column,
+            end: last.end,
+            end_line: last.end_line,
+            end_column: last.end_column,
+        }
+    }
+    /// Whether position `at` is inside the span.
+    pub fn contains(&self, at: usize) -> bool {
+        self.start <= at && at < self.end
+    }
+    /// The part of `input`, the whole input that was parsed, the span
+    /// covers.
+    pub fn slice<'s, S: ?Sized + Input>(&self, input: &'s S) -> &'s S {
+        input.slice(self.start, self.end)
+    }
+}
+
+/// A parse tree. Every node carries the `Span` it was parsed from, but
+/// comparing trees ignores spans, so that a tree can be checked against one
+/// written by hand.
+#[derive(Debug, Eq)]
+pub enum NonTerminal<'a, T: Identifier, S: ?Sized + Input = str> {
+    Node {
+        identifier: T,
+        children: Box<Self>,
+        span: Span,
+    },
+    Congregate(Vec<Self>, Span),
+    // this imposes that the State lives as long
     // as this nonterminal
-    Leaf(&'a str),
-    Empty,
+    Leaf(&'a S, Span),
+    Empty(Span),
+    /// Input skipped by `recover()` after `error`, up to a synchronization
+    /// point.
+    Error {
+        error: Box<ParseError<'a, T, S>>,
+        skipped: &'a S,
+        span: Span,
+    },
+}
+impl<T: Identifier + PartialEq, S: ?Sized + Input> PartialEq for NonTerminal<'_, T, S> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::Node {
+                    identifier: a,
+                    children: m,
+                    ..
+                },
+                Self::Node {
+                    identifier: b,
+                    children: n,
+                    ..
+                },
+            ) => a == b && m == n,
+            (Self::Congregate(m, _), Self::Congregate(n, _)) => m == n,
+            (Self::Leaf(a, _), Self::Leaf(b, _)) => a == b,
+            (Self::Empty(_), Self::Empty(_)) => true,
+            (
+                Self::Error {
+                    error: e,
+                    skipped: a,
+                    ..
+                },
+                Self::Error {
+                    error: f,
+                    skipped: b,
+                    ..
+                },
+            ) => e == f && a == b,
+            _ => false,
+        }
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Clone for NonTerminal<'_, T, S> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Node {
+                identifier,
+                children,
+                span,
+            } => Self::Node {
+                identifier: *identifier,
+                children: children.clone(),
+                span: *span,
+            },
+            Self::Congregate(v, span) => Self::Congregate(v.clone(), *span),
+            Self::Leaf(s, span) => Self::Leaf(s, *span),
+            Self::Empty(span) => Self::Empty(*span),
+            Self::Error {
+                error,
+                skipped,
+                span,
+            } => Self::Error {
+                error: error.clone(),
+                skipped,
+                span: *span,
+            },
+        }
+    }
 }
-impl<'a, T: Identifier> NonTerminal<'a, T> {
+impl<'a, T: Identifier, S: ?Sized + Input> NonTerminal<'a, T, S> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Node { span, .. }
+            | Self::Congregate(_, span)
+            | Self::Leaf(_, span)
+            | Self::Empty(span)
+            | Self::Error { span, .. } => *span,
+        }
+    }
     pub fn clean(self) -> Self {
         match self {
             Self::Node {
                 identifier,
                 children,
+                span,
             } => Self::Node {
                 identifier,
                 children: Box::new(children.clean()),
+                span,
             },
-            Self::Congregate(v) => {
+            Self::Congregate(v, span) => {
                 let mut i = vec![];
                 for c in v.into_iter().map(|n| n.clean()) {
                     match c {
-                        Self::Empty => {}
+                        Self::Empty(_) => {}
                         _ => i.push(c),
                     }
                 }
                 if i.is_empty() {
-                    Self::Empty
+                    Self::Empty(span)
                 } else {
-                    Self::Congregate(i)
+                    Self::Congregate(i, span)
                 }
             }
             e => e,
         }
     }
+    /// Every error recovered from in this tree, in input order.
+    pub fn errors(&self) -> Vec<&ParseError<'a, T, S>> {
+        match self {
+            Self::Node { children, .. } => children.errors(),
+            Self::Congregate(v, _) => v.iter().flat_map(|n| n.errors()).collect(),
+            Self::Error { error, .. } => vec![error.as_ref()],
+            _ => vec![],
+        }
+    }
+}
+
+/// State shared by every parser during a single run over some input.
+///
+/// A context is optional: `State::new` parses without one, while
+/// `State::with_context` threads it through every parser so they can
+/// share the packrat table.
+pub struct Context {
+    pub(crate) memo: Memo,
+    furthest: RefCell<Option<Furthest>>,
+    frames: RefCell<Vec<Frame>>,
+}
+
+// a point the running parse may backtrack to
+struct Frame {
+    head: usize,
+    committable: bool,
+    committed: bool,
+}
+impl Context {
+    /// A context that only memoizes parsers wrapped with `.memo()`.
+    pub fn new() -> Self {
+        Context {
+            memo: Memo::new(false),
+            furthest: RefCell::new(None),
+            frames: RefCell::new(Vec::new()),
+        }
+    }
+    /// A context that memoizes every parser of the grammar.
+    pub fn packrat() -> Self {
+        Context {
+            memo: Memo::new(true),
+            furthest: RefCell::new(None),
+            frames: RefCell::new(Vec::new()),
+        }
+    }
+    pub fn memo_stats(&self) -> MemoStats {
+        self.memo.stats()
+    }
+    /// What the parsers of the last run expected at the furthest position
+    /// where one of them failed.
+    pub fn expected(&self) -> Option<Expected> {
+        self.furthest.borrow().as_ref().map(|f| f.expected.clone())
+    }
+    pub(crate) fn take_furthest(&self) -> Option<Furthest> {
+        self.furthest.take()
+    }
+    /// Keeps whichever of `other` and the current furthest failure is
+    /// further, or both expectations when they are at the same position.
+    pub(crate) fn merge_furthest(&self, other: Option<Furthest>) {
+        let Some(other) = other else { return };
+        let mut furthest = self.furthest.borrow_mut();
+        match furthest.as_mut() {
+            Some(f) if f.head > other.head => {}
+            Some(f) if f.head == other.head => {
+                for item in other.expected.items {
+                    if !f.expected.items.contains(&item) {
+                        f.expected.items.push(item);
+                    }
+                }
+            }
+            _ => *furthest = Some(other),
+        }
+    }
+    /// Applies `edits` to `text`, the input of the previous run with this
+    /// context, and returns the edited text. Each edit replaces a range of
+    /// bytes with new text, in terms of the text left by the edits before it.
+    ///
+    /// Memoized results that did not look at an edited range are kept for
+    /// the next run over the returned text, shifted if they came after it, so
+    /// that only the parts of the grammar that saw the edits run again. The
+    /// result is the same as with a fresh context.
+    pub fn edit(&mut self, text: &str, edits: &[(Range<usize>, &str)]) -> String {
+        let mut text = text.to_owned();
+        for (range, new) in edits {
+            let new_end = range.start + new.len();
+            let end_at = text[..range.end].locate(0, 0);
+            text.replace_range(range.clone(), new);
+            self.memo.edit(&Shift {
+                start: range.start,
+                end: range.end,
+                new_end,
+                end_at,
+                new_end_at: text[..new_end].locate(0, 0),
+            });
+        }
+        text
+    }
+}
+impl std::fmt::Debug for Context {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Context {:?}", self.memo_stats())
+    }
+}
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-#[derive(Clone, Copy)]
-pub struct StrState<'a> {
-    pub string: &'a str,
+/// Position of a parser in its input.
+pub struct State<'a, S: ?Sized + Input = str> {
+    pub string: &'a S,
     pub(crate) head: usize,
     pub(crate) column: usize,
     pub(crate) line: usize,
+    pub(crate) ctx: Option<&'a Context>,
+    // whether a cut was passed since the enclosing choice started; on an
+    // error, whether the failure is committed
+    pub(crate) cut: bool,
+    // what the enclosing `skipping()` skips, unless inside `atomic()`
+    pub(crate) trivia: Option<&'a dyn Skip<S>>,
+    // the indentation of the innermost block, see `indent`
+    pub(crate) indent: Level,
+}
+pub type StrState<'a> = State<'a, str>;
+pub type ByteState<'a> = State<'a, [u8]>;
+pub type TokenState<'a, K> = State<'a, [K]>;
+impl<S: ?Sized + Input> Clone for State<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
 }
-impl<'a> StrState<'a> {
-    pub fn new(s: &'a str) -> Self {
-        StrState {
+impl<S: ?Sized + Input> Copy for State<'_, S> {}
+impl<'a, S: ?Sized + Input> State<'a, S> {
+    pub fn new(s: &'a S) -> Self {
+        State {
             string: s,
             head: 0,
             column: 0,
             line: 0,
+            ctx: None,
+            cut: false,
+            trivia: None,
+            indent: Level::default(),
+        }
+    }
+    /// Starts a run with `ctx`, forgetting what the last run expected.
+    pub fn with_context(s: &'a S, ctx: &'a Context) -> Self {
+        ctx.furthest.take();
+        State {
+            ctx: Some(ctx),
+            ..Self::new(s)
         }
     }
     pub fn is_empty(&self) -> bool {
         self.string.len() == self.head
     }
     pub fn line_of(&self) -> &'a str {
-        self.string.lines().nth(self.line).unwrap()
+        self.string.line_of(self.line)
+    }
+    /// Records that a parser looked at the input up to, but excluding, `to`,
+    /// which incremental reparsing needs to know. Primitives must call this
+    /// for everything they look at, counting the end of input as one more
+    /// position.
+    pub(crate) fn reached(&self, to: usize) {
+        if let Some(ctx) = self.ctx {
+            ctx.memo.reached(to);
+        }
+    }
+    /// The same position in a new choice scope, where no cut was passed yet.
+    pub(crate) fn uncut(self) -> Self {
+        State { cut: false, ..self }
     }
+    /// Runs `f` as a point the parse may backtrack to, which keeps memoized
+    /// results from here on until it returns, or until a cut commits it if
+    /// it is `committable`.
+    pub(crate) fn backtrack<R>(&self, committable: bool, f: impl FnOnce() -> R) -> R {
+        let Some(ctx) = self.ctx else { return f() };
+        ctx.frames.borrow_mut().push(Frame {
+            head: self.head,
+            committable,
+            committed: false,
+        });
+        let r = f();
+        ctx.frames.borrow_mut().pop();
+        r
+    }
+    /// Commits the innermost backtrack point, and releases the memoized
+    /// results no open one can reach any more.
+    pub(crate) fn commit(&self) {
+        let Some(ctx) = self.ctx else { return };
+        let mut frames = ctx.frames.borrow_mut();
+        if let Some(f) = frames.last_mut() {
+            f.committed |= f.committable;
+        }
+        let below = frames
+            .iter()
+            .filter(|f| !f.committed)
+            .map(|f| f.head)
+            .fold(self.head, usize::min);
+        drop(frames);
+        ctx.memo.release(below);
+    }
+    /// Records that a parser failed here, expecting `what`.
+    pub(crate) fn expected(&self, what: impl FnOnce() -> String) {
+        let Some(ctx) = self.ctx else { return };
+        if matches!(ctx.furthest.borrow().as_ref(), Some(f) if f.head > self.head) {
+            return;
+        }
+        ctx.merge_furthest(Some(Furthest {
+            head: self.head,
+            expected: Expected {
+                line: self.line,
+                column: self.column,
+                items: vec![what()],
+            },
+        }));
+    }
+    /// Skips the trivia at this state, if any is skipped here, adding it to
+    /// `children` when kept. Trivia failing for good fails `p`.
+    pub(crate) fn skip_trivia<T: Identifier>(
+        self,
+        p: &'a dyn Parser<T, S>,
+        children: &mut Vec<NonTerminal<'a, T, S>>,
+    ) -> Result<Self, ParseError<'a, T, S>> {
+        let Some(trivia) = self.trivia else {
+            return Ok(self);
+        };
+        match trivia.skip(self) {
+            Ok((s, keep)) => {
+                if keep && s.head > self.head {
+                    children.push(NonTerminal::Leaf(
+                        self.string.slice(self.head, s.head),
+                        Span::between(&self, &s),
+                    ));
+                }
+                Ok(s)
+            }
+            Err((s, msg)) => Err(ParseError {
+                location: (s.line_of(), s.line, s.column),
+                head: s.head,
+                expected: p,
+                backtrace: ErrorBacktrace::Empty,
+                msg,
+                ctx: self.ctx,
+            }),
+        }
+    }
+    /// The state `n` positions further. Advancing by nothing is allowed
+    /// anywhere, the end of input included.
     pub fn advance(mut self, n: usize) -> Self {
+        if n == 0 {
+            return self;
+        }
         assert!(
             self.string.len() > self.head,
-            "Called method advance on StrState when it's already empty!"
+            "Called method advance on State when it's already empty!"
         );
-        for c in self.string[self.head..self.head + n].chars() {
-            if c == '\n' {
-                self.column = 0;
-                self.line += 1;
-            } else {
-                self.column += 1;
-            }
-        }
+        (self.line, self.column) = self
+            .string
+            .slice(self.head, self.head + n)
+            .locate(self.line, self.column);
         self.head += n;
-        return self;
+        self
     }
 }
-impl<'a> Deref for StrState<'a> {
-    type Target = str;
+impl<S: ?Sized + Input> Deref for State<'_, S> {
+    type Target = S;
 
     fn deref(&self) -> &Self::Target {
-        &self.string[self.head..]
+        self.string.slice(self.head, self.string.len())
     }
 }
-impl std::fmt::Debug for StrState<'_> {
+impl<S: ?Sized + Input> std::fmt::Debug for State<'_, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self.deref())
     }
 }
 
-pub trait Parser<T: Identifier>: std::fmt::Debug {
-    fn run<'a>(
-        &'a self,
-        input: StrState<'a>,
-    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)>;
-    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>>;
-    fn msg(self: Box<Self>, msg: &'static str) -> Box<dyn Parser<T>> {
+/// Trivia skipped between the elements of sequences and repetitions, see
+/// `Parser::skipping`.
+pub(crate) trait Skip<S: ?Sized + Input> {
+    fn skip<'a>(&'a self, input: State<'a, S>) -> Skipped<'a, S>;
+}
+
+pub trait Parser<T: Identifier, S: ?Sized + Input = str>: std::fmt::Debug {
+    // parsers return their error along with the state they failed at, by
+    // value, which is larger than clippy likes
+    #[allow(clippy::result_large_err)]
+    fn run<'a>(&'a self, input: State<'a, S>) -> ParseResult<'a, T, S>;
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>>;
+    fn msg(self: Box<Self>, msg: &'static str) -> Box<dyn Parser<T, S>> {
         Box::new(ParserMsg {
             recipe: self.to_dyn(),
             msg,
         })
     }
-    fn label(self: Box<Self>, ident: T) -> Box<dyn Parser<T>> {
+    fn label(self: Box<Self>, ident: T) -> Box<dyn Parser<T, S>> {
         Box::new(ParserLabeled {
             recipe: self.to_dyn(),
             label: ident,
         })
     }
-    fn seq(self: Box<Self>, p: Box<dyn Parser<T>>) -> Box<dyn Parser<T>> {
+    fn seq(self: Box<Self>, p: Box<dyn Parser<T, S>>) -> Box<dyn Parser<T, S>> {
         Box::new(ParserSeq {
             recipe: vec![self.to_dyn(), p],
         })
     }
-    fn or(self: Box<Self>, p: Box<dyn Parser<T>>) -> Box<dyn Parser<T>> {
+    fn or(self: Box<Self>, p: Box<dyn Parser<T, S>>) -> Box<dyn Parser<T, S>> {
         Box::new(ParserChoice {
             recipe: vec![self.to_dyn(), p],
         })
     }
-    fn atleast_once(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn atleast_once(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         Box::new(ParserPlus {
             recipe: self.to_dyn(),
         })
     }
-    fn multiple(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn multiple(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         Box::new(ParserStar {
             recipe: self.to_dyn(),
         })
     }
-    fn maybe(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn maybe(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         Box::new(ParserMaybe {
             recipe: self.to_dyn(),
         })
     }
-    fn ensure(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn ensure(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         Box::new(ParserEnsure {
             recipe: self.to_dyn(),
         })
     }
-    fn avoid(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn avoid(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         Box::new(ParserAvoid {
             recipe: self.to_dyn(),
         })
     }
-    fn catenate(self: Box<Self>) -> Box<dyn Parser<T>> {
-        Box::new(ParserCatenate {
+    /// Reports this parser as expecting `name` when it fails without getting
+    /// past its start, instead of listing what its parts expected.
+    fn name(self: Box<Self>, name: &'static str) -> Box<dyn Parser<T, S>> {
+        Box::new(ParserName {
             recipe: self.to_dyn(),
+            name,
         })
     }
-    fn log(
+    /// Commits the enclosing choice to the current alternative once this
+    /// parser matched: if anything after it in that alternative fails, the
+    /// error is final. No choice tries its other alternatives over it, and
+    /// repetitions and `maybe()` fail with it instead of stopping, unless
+    /// `recover()` catches it. `maybe()` and every round of a repetition
+    /// count as choices.
+    ///
+    /// With a context, memoized results before the cut are released when no
+    /// backtrack point can reach them any more.
+    fn cut(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        Box::new(ParserCut {
+            recipe: self.to_dyn(),
+        })
+    }
+    /// On failure, skips input until `sync` matches, or to the end of input,
+    /// and yields an `Error` node holding the error and everything skipped,
+    /// the match of `sync` included. The parse goes on from there, and
+    /// `NonTerminal::errors()` collects what was recovered from.
+    ///
+    /// Failing where there is nothing left to skip still fails.
+    fn recover(self: Box<Self>, sync: Box<dyn Parser<T, S>>) -> Box<dyn Parser<T, S>> {
+        Box::new(ParserRecover {
+            recipe: self.to_dyn(),
+            sync,
+        })
+    }
+    /// Skips `trivia`, such as whitespace and comments, between the elements
+    /// of every sequence and repetition within this parser, and before and
+    /// after it. Skipped trivia is left out of the tree. Trivia failing with
+    /// a cut, such as an unclosed block comment, fails the parse there.
+    fn skipping(self: Box<Self>, trivia: Box<dyn Parser<T, S>>) -> Box<dyn Parser<T, S>> {
+        Box::new(ParserSkipping {
+            recipe: self.to_dyn(),
+            trivia,
+            keep: false,
+        })
+    }
+    /// Like `skipping`, keeping each stretch of skipped trivia in the tree as
+    /// a leaf.
+    fn skipping_kept(self: Box<Self>, trivia: Box<dyn Parser<T, S>>) -> Box<dyn Parser<T, S>> {
+        Box::new(ParserSkipping {
+            recipe: self.to_dyn(),
+            trivia,
+            keep: true,
+        })
+    }
+    /// Parses this parser as a single token, without skipping trivia inside
+    /// it, for identifiers, numbers and the like under `skipping`.
+    fn atomic(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        Box::new(ParserAtomic {
+            recipe: self.to_dyn(),
+        })
+    }
+    /// Parses this parser any number of times, separated by `sep`, into a
+    /// flat list of the items and separators. `trailing` tells whether the
+    /// list may end with a separator.
+    fn sep_by(
+        self: Box<Self>,
+        sep: Box<dyn Parser<T, S>>,
+        trailing: Trailing,
+    ) -> Box<dyn Parser<T, S>> {
+        Box::new(ParserSepBy {
+            item: self.to_dyn(),
+            sep,
+            trailing,
+            least: 0,
+        })
+    }
+    /// Like `sep_by`, parsing at least one item.
+    fn sep_by1(
         self: Box<Self>,
-        logger: Box<
-            dyn Fn(
-                &Result<(NonTerminal<'_, T>, StrState<'_>), (ParseError<'_, T>, StrState<'_>)>,
-            ) -> (),
-        >,
-    ) -> Box<dyn Parser<T>> {
+        sep: Box<dyn Parser<T, S>>,
+        trailing: Trailing,
+    ) -> Box<dyn Parser<T, S>> {
+        Box::new(ParserSepBy {
+            item: self.to_dyn(),
+            sep,
+            trailing,
+            least: 1,
+        })
+    }
+    fn catenate(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        Box::new(ParserCatenate {
+            recipe: self.to_dyn(),
+        })
+    }
+    fn log(self: Box<Self>, logger: Logger<T, S>) -> Box<dyn Parser<T, S>> {
         Box::new(ParserLog {
             recipe: self.to_dyn(),
             logger,
         })
     }
-    fn ignore(self: Box<Self>) -> Box<dyn Parser<T>> {
+    fn ignore(self: Box<Self>) -> Box<dyn Parser<T, S>> {
         Box::new(ParserIgnoreRes {
             recipe: self.to_dyn(),
         })
     }
+    fn memo(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        Box::new(ParserMemo {
+            recipe: self.to_dyn(),
+        })
+    }
 }
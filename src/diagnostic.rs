@@ -0,0 +1,276 @@
+// Diagnostic rendering.
+//
+// A `Diagnostic` is a message about some source text, with labels pointing
+// at spans of it, notes and help. Rendering shows every labelled line along
+// with a few lines around it, behind a gutter of line numbers, and underlines
+// each label below its line:
+//
+//   error: unclosed call
+//    --> 2:5
+//     |
+//   1 | let a =
+//   2 |     foo(2, 3
+//     |     ^^^ this call
+//     |            - expected `)`
+//     |
+//     = help: add `)`
+//
+// Underlines follow what a terminal shows: tabs are expanded and wide
+// characters take up two columns.
+
+use crate::core::{Identifier, Input, ParseError, Span};
+
+const TAB: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Label {
+    span: Span,
+    text: String,
+    primary: bool,
+}
+
+/// A message about some source text, rendered with `render()`.
+///
+/// Labels are placed by the lines and columns of their spans. A label
+/// spanning several lines is underlined up to the end of its first line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    message: String,
+    labels: Vec<Label>,
+    notes: Vec<String>,
+    help: Vec<String>,
+    context: usize,
+    color: bool,
+}
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Diagnostic {
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: Vec::new(),
+            context: 1,
+            color: false,
+        }
+    }
+    /// Points at what the diagnostic is about.
+    pub fn primary(mut self, span: Span, text: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            text: text.into(),
+            primary: true,
+        });
+        self
+    }
+    /// Points at something related, such as where a construct started.
+    pub fn secondary(mut self, span: Span, text: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            text: text.into(),
+            primary: false,
+        });
+        self
+    }
+    pub fn note(mut self, text: impl Into<String>) -> Self {
+        self.notes.push(text.into());
+        self
+    }
+    pub fn help(mut self, text: impl Into<String>) -> Self {
+        self.help.push(text.into());
+        self
+    }
+    /// Number of lines shown before and after every labelled line.
+    pub fn context(mut self, lines: usize) -> Self {
+        self.context = lines;
+        self
+    }
+    /// Whether to colour the output with ANSI escapes. Off by default, which
+    /// gives plain text fit for comparing in tests.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+    /// The diagnostic about `source`, the text its spans were taken from.
+    /// Lines and columns are shown counting from 1, as editors do.
+    pub fn render(&self, source: &str) -> String {
+        let lines: Vec<&str> = source
+            .split('\n')
+            .map(|l| l.strip_suffix('\r').unwrap_or(l))
+            .collect();
+        let mut out = format!(
+            "{}{}\n",
+            self.paint(ERROR, "error"),
+            self.paint(BOLD, &format!(": {}", self.message))
+        );
+        // the empty line after a final newline is only shown when labelled
+        let mut last = lines.len() - 1;
+        if last > 0 && lines[last].is_empty() && self.labels.iter().all(|l| l.span.line < last) {
+            last -= 1;
+        }
+        let mut shown: Vec<usize> = Vec::new();
+        for l in self.labels.iter() {
+            let from = l.span.line.saturating_sub(self.context);
+            let to = (l.span.end_line.max(l.span.line) + self.context).min(last);
+            shown.extend(from..=to);
+        }
+        shown.sort_unstable();
+        shown.dedup();
+        let pad = " ".repeat(shown.last().map_or(0, |l| (l + 1).to_string().len()));
+        let gutter = self.paint(GUTTER, &format!("{pad} |"));
+        if let Some(l) = self
+            .labels
+            .iter()
+            .find(|l| l.primary)
+            .or(self.labels.first())
+        {
+            out += &format!(
+                "{}{} {}:{}\n",
+                pad,
+                self.paint(GUTTER, "-->"),
+                l.span.line + 1,
+                l.span.column + 1
+            );
+        }
+        if !shown.is_empty() {
+            out += &format!("{gutter}\n");
+        }
+        for (i, &n) in shown.iter().enumerate() {
+            if i > 0 && n > shown[i - 1] + 1 {
+                out += &format!("{}\n", self.paint(GUTTER, "..."));
+            }
+            let (text, columns) = display(lines[n]);
+            let number = format!("{:>w$} |", n + 1, w = pad.len());
+            out += &self.paint(GUTTER, &number);
+            if !text.is_empty() {
+                out += &format!(" {text}");
+            }
+            out.push('\n');
+            let mut labels: Vec<&Label> = self.labels.iter().filter(|l| l.span.line == n).collect();
+            labels.sort_by_key(|l| l.span.column);
+            for l in labels {
+                let end = match l.span.end_line == n {
+                    true => l.span.end_column,
+                    false => columns.len() - 1,
+                };
+                let from = at(&columns, l.span.column);
+                let to = at(&columns, end).max(from + 1);
+                let (code, mark) = match l.primary {
+                    true => (ERROR, "^"),
+                    false => (SECONDARY, "-"),
+                };
+                let underline = format!(
+                    "{}{}",
+                    mark.repeat(to - from),
+                    match l.text.as_str() {
+                        "" => String::new(),
+                        t => format!(" {t}"),
+                    }
+                );
+                out += &format!(
+                    "{gutter} {}{}\n",
+                    " ".repeat(from),
+                    self.paint(code, &underline)
+                );
+            }
+        }
+        if !shown.is_empty() && (!self.notes.is_empty() || !self.help.is_empty()) {
+            out += &format!("{gutter}\n");
+        }
+        for (kind, texts) in [("note", &self.notes), ("help", &self.help)] {
+            for t in texts {
+                out += &format!("{pad} {} {t}\n", self.paint(BOLD, &format!("= {kind}:")));
+            }
+        }
+        out
+    }
+    fn paint(&self, code: &str, text: &str) -> String {
+        match self.color {
+            true => format!("\x1b[{code}m{text}\x1b[0m"),
+            false => text.to_owned(),
+        }
+    }
+}
+
+const ERROR: &str = "1;31";
+const SECONDARY: &str = "1;34";
+const GUTTER: &str = "1;34";
+const BOLD: &str = "1";
+
+impl<T: Identifier, S: ?Sized + Input> ParseError<'_, T, S> {
+    /// A diagnostic pointing at where parsing failed, and at what the run
+    /// expected when it kept track of that. Render it with the whole input.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let point = |line, column| Span {
+            line,
+            column,
+            end_line: line,
+            end_column: column,
+            ..Span::default()
+        };
+        let here = point(self.location.1, self.location.2);
+        let d = Diagnostic::new(self.msg.unwrap_or("parsing failed"));
+        match self.expected() {
+            Some(x) if (x.line, x.column) == (self.location.1, self.location.2) => {
+                d.primary(here, x.describe())
+            }
+            Some(x) => d
+                .primary(here, "")
+                .secondary(point(x.line, x.column), x.describe()),
+            None => d.primary(here, ""),
+        }
+    }
+}
+
+/// `line` as a terminal shows it, with tabs expanded, and the display column
+/// at which each of its characters starts, followed by that of its end.
+pub(crate) fn display(line: &str) -> (String, Vec<usize>) {
+    let mut text = String::new();
+    let mut columns = Vec::new();
+    let mut column = 0;
+    for c in line.chars() {
+        columns.push(column);
+        if c == '\t' {
+            let n = TAB - column % TAB;
+            text += &" ".repeat(n);
+            column += n;
+        } else {
+            text.push(c);
+            column += width(c);
+        }
+    }
+    columns.push(column);
+    (text, columns)
+}
+
+// display column of character `i`, counting past the end of the line as
+// one column per character
+fn at(columns: &[usize], i: usize) -> usize {
+    match columns.get(i) {
+        Some(c) => *c,
+        None => columns[columns.len() - 1] + i + 1 - columns.len(),
+    }
+}
+
+// columns taken up by `c` on a terminal
+fn width(c: char) -> usize {
+    match c as u32 {
+        // combining marks and zero width characters
+        0x0300..=0x036F | 0x200B..=0x200F | 0x20D0..=0x20FF | 0xFE00..=0xFE0F => 0,
+        // east asian wide and fullwidth characters, and emoji
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
@@ -0,0 +1,242 @@
+// Indentation-sensitive parsing.
+//
+// Blocks are delimited by indentation, as in Python and YAML. `indent(body)`
+// parses a block indented deeper than the enclosing one, running `body` at
+// the block's level; `same_indent()` starts each further line of the block,
+// and `dedent()` tells where the block ends:
+//
+// block <- indent(stmt (same_indent stmt)*)
+//
+// The levels of the enclosing blocks form a stack, with the innermost level
+// held in the states like the head, so that backtracking restores it. Lines
+// holding nothing but spaces and tabs are blank, and skipped. The indentation
+// of a line is either spaces or tabs, and has to be made of the same as the
+// block it is in.
+
+use std::fmt::Debug;
+
+use crate::core::{ErrorBacktrace, Identifier, NonTerminal, ParseError, Parser, Span, StrState};
+use crate::memo::apply;
+
+/// The indentation of the innermost block.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Level {
+    width: usize,
+    tabs: bool,
+}
+
+/// Parses a block indented deeper than the current one, from the start of
+/// one of its lines: skips blank lines and the indentation of the first
+/// line, then runs `body` with the block's level as the current one. The
+/// tree is that of `body`.
+pub fn indent<T: Identifier>(body: Box<dyn Parser<T>>) -> Box<dyn Parser<T>> {
+    Box::new(ParserIndent(body))
+}
+/// Parses the blank lines and the indentation of the next line, which has
+/// to be indented as much as the current block.
+pub fn same_indent<T: Identifier>() -> Box<dyn Parser<T>> {
+    Box::new(ParserSameIndent)
+}
+/// Parses nothing, succeeding if the next line that is not blank is
+/// indented less than the current block, or if there is none, which may be
+/// checked from anywhere on the last line.
+pub fn dedent<T: Identifier>() -> Box<dyn Parser<T>> {
+    Box::new(ParserDedent)
+}
+
+// The next line at `input` that is not blank: where it starts and the level
+// of its indentation, or nothing at the end of input. Indentation mixing
+// spaces and tabs with each other or with the current block's fails where
+// it does.
+type Line<'a> = Result<Option<(StrState<'a>, Level)>, (StrState<'a>, &'static str)>;
+fn next_line(input: StrState<'_>) -> Line<'_> {
+    let s: &str = &input;
+    let mut start = 0;
+    loop {
+        let rest = &s[start..];
+        let width = rest.find(|c| c != ' ' && c != '\t').unwrap_or(rest.len());
+        let after = &rest[width..];
+        if after.is_empty() {
+            input.reached(input.head + s.len() + 1);
+            return Ok(None);
+        }
+        let blank = ["\n", "\r\n"].into_iter().find(|nl| after.starts_with(nl));
+        if let Some(nl) = blank {
+            start += width + nl.len();
+            continue;
+        }
+        input.reached(input.head + start + width + 1);
+        let line = input.advance(start);
+        let indentation = &rest[..width];
+        let tabs = indentation.starts_with('\t');
+        let mixed = match tabs {
+            true => indentation.find(' '),
+            false => indentation.find('\t'),
+        };
+        if let Some(i) = mixed {
+            return Err((line.advance(i), "Mixed tabs and spaces in indentation!"));
+        }
+        if width > 0 && input.indent.width > 0 && tabs != input.indent.tabs {
+            return Err((line, "Indentation mixes tabs and spaces with its block!"));
+        }
+        return Ok(Some((line, Level { width, tabs })));
+    }
+}
+
+// the error of `p` failing at `at` for a parse from `input`, committed if
+// the indentation was mixed
+#[allow(clippy::result_large_err)]
+fn fail<'a, T: Identifier>(
+    p: &'a dyn Parser<T>,
+    input: StrState<'a>,
+    at: StrState<'a>,
+    msg: Option<&'static str>,
+    mixed: bool,
+) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+    Err((
+        ParseError {
+            location: (at.line_of(), at.line, at.column),
+            head: at.head,
+            expected: p,
+            backtrace: ErrorBacktrace::Empty,
+            msg,
+            ctx: input.ctx,
+        },
+        StrState {
+            cut: input.cut || mixed,
+            ..input
+        },
+    ))
+}
+
+pub struct ParserIndent<T>(Box<dyn Parser<T>>);
+impl<T: Identifier> Debug for ParserIndent<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "indented block of {:?}", self.0)
+    }
+}
+impl<T: Identifier> Parser<T> for ParserIndent<T> {
+    fn run<'a>(
+        &'a self,
+        input: StrState<'a>,
+    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        if input.column > 0 {
+            return fail(
+                self,
+                input,
+                input,
+                Some("Not at the start of a line!"),
+                false,
+            );
+        }
+        let (line, level) = match next_line(input) {
+            Ok(Some((line, level))) if level.width > input.indent.width => (line, level),
+            Ok(found) => {
+                let at = found.map_or(input, |(line, _)| line);
+                at.expected(|| "indented block".to_owned());
+                return fail(self, input, at, Some("Expected an indented block!"), false);
+            }
+            Err((at, msg)) => return fail(self, input, at, Some(msg), true),
+        };
+        let inside = StrState {
+            indent: level,
+            ..line.advance(level.width)
+        };
+        match apply(self.0.as_ref(), inside) {
+            Ok((n, s)) => Ok((
+                n,
+                StrState {
+                    indent: input.indent,
+                    ..s
+                },
+            )),
+            Err((e, s)) => Err((
+                e,
+                StrState {
+                    cut: s.cut || input.cut,
+                    ..input
+                },
+            )),
+        }
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+        self
+    }
+}
+
+pub struct ParserSameIndent;
+impl Debug for ParserSameIndent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse a line of the current block")
+    }
+}
+impl<T: Identifier> Parser<T> for ParserSameIndent {
+    fn run<'a>(
+        &'a self,
+        input: StrState<'a>,
+    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        if input.column > 0 {
+            return fail(
+                self,
+                input,
+                input,
+                Some("Not at the start of a line!"),
+                false,
+            );
+        }
+        let (line, level) = match next_line(input) {
+            Ok(Some(found)) => found,
+            Ok(None) => {
+                input.expected(|| "line of the block".to_owned());
+                return fail(self, input, input, Some("At end of input!"), false);
+            }
+            Err((at, msg)) => return fail(self, input, at, Some(msg), true),
+        };
+        let msg = match level.width.cmp(&input.indent.width) {
+            std::cmp::Ordering::Equal => {
+                let s = input.advance(line.head + level.width - input.head);
+                return Ok((NonTerminal::Empty(Span::between(&input, &s)), s));
+            }
+            std::cmp::Ordering::Greater => "Unexpected indentation!",
+            std::cmp::Ordering::Less => "Unexpected end of block!",
+        };
+        line.expected(|| "line of the block".to_owned());
+        fail(self, input, line, Some(msg), false)
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+        self
+    }
+}
+
+pub struct ParserDedent;
+impl Debug for ParserDedent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse the end of the current block")
+    }
+}
+impl<T: Identifier> Parser<T> for ParserDedent {
+    fn run<'a>(
+        &'a self,
+        input: StrState<'a>,
+    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        match next_line(input) {
+            Ok(None) => Ok((NonTerminal::Empty(Span::at(&input)), input)),
+            Ok(Some(_)) if input.column > 0 => fail(
+                self,
+                input,
+                input,
+                Some("Not at the start of a line!"),
+                false,
+            ),
+            Ok(Some((_, level))) if level.width >= input.indent.width => {
+                input.expected(|| "end of block".to_owned());
+                fail(self, input, input, None, false)
+            }
+            Ok(Some(_)) => Ok((NonTerminal::Empty(Span::at(&input)), input)),
+            Err((at, msg)) => fail(self, input, at, Some(msg), true),
+        }
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+        self
+    }
+}
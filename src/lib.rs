@@ -1,9 +1,25 @@
+// lets the code generated by `grammar!` refer to `::page` from within
+extern crate self as page;
+
+pub mod charset;
 pub mod combinators;
 pub mod core;
+pub mod diagnostic;
+pub mod indent;
+pub mod memo;
+pub mod number;
+pub mod peg;
+pub mod pratt;
 pub mod primitives;
+pub mod quoted;
+pub mod regex;
+pub mod rule;
+pub mod stream;
+pub mod trivia;
+pub mod typed;
+mod unicode;
 
-// TODO:
-//  1. Add memoization
+pub use page_macros::grammar;
 
 #[cfg(test)]
 mod tests {
@@ -11,6 +27,16 @@ mod tests {
 
     use super::*;
 
+    // trees compare equal whatever their spans, so expected ones use this
+    const ANY: core::Span = core::Span {
+        start: 0,
+        end: 0,
+        line: 0,
+        column: 0,
+        end_line: 0,
+        end_column: 0,
+    };
+
     #[test]
     fn pchar_passes() {
         #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -20,7 +46,7 @@ mod tests {
         let s = core::StrState::new("c");
         if let Ok((r, s)) = p.run(s) {
             assert!(s.is_empty());
-            assert!(r == core::NonTerminal::<I>::Leaf("c"));
+            assert!(r == core::NonTerminal::<I>::Leaf("c", ANY));
         } else {
             panic!("Parser failed!");
         }
@@ -35,24 +61,315 @@ mod tests {
         let s = core::StrState::new("damn huh");
         if let Ok((r, s)) = p.run(s) {
             assert!(s.deref() == " huh");
-            assert!(r == core::NonTerminal::<I>::Leaf("damn"));
+            assert!(r == core::NonTerminal::<I>::Leaf("damn", ANY));
         } else {
             panic!("Parser failed!");
         }
     }
     #[test]
+    fn nocase_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        struct I;
+        impl core::Identifier for I {}
+        use crate::core::NonTerminal::*;
+        use primitives::*;
+        // the leaf matched in `text`, and what is left after it
+        fn leaf<'a>(p: &'a dyn core::Parser<I>, text: &'a str) -> Option<(&'a str, &'a str)> {
+            match p.run(core::StrState::new(text)) {
+                Ok((Leaf(l, _), s)) => Some((l, &text[s.head..])),
+                Ok((r, _)) => panic!("r was: {:?}", r),
+                Err(_) => None,
+            }
+        }
+        let select = pstr_nocase::<I>("select");
+        assert!(leaf(select.as_ref(), "SeLeCT *") == Some(("SeLeCT", " *")));
+        assert!(leaf(select.as_ref(), "SELEC").is_none());
+        let street = pstr_nocase::<I>("Straße");
+        assert!(leaf(street.as_ref(), "STRASSE 1") == Some(("STRASSE", " 1")));
+        assert!(leaf(street.as_ref(), "strasse") == Some(("strasse", "")));
+        let e = pchar_nocase::<I>('é');
+        assert!(leaf(e.as_ref(), "Été") == Some(("É", "té")));
+        assert!(leaf(e.as_ref(), "e").is_none());
+        let keyword = pkeyword::<I>(["select", "sel", "FROM"]);
+        assert!(leaf(keyword.as_ref(), "SELECT x") == Some(("SELECT", " x")));
+        assert!(leaf(keyword.as_ref(), "Sel(x)") == Some(("Sel", "(x)")));
+        assert!(leaf(keyword.as_ref(), "from") == Some(("from", "")));
+        assert!(leaf(keyword.as_ref(), "selection").is_none());
+        assert!(leaf(keyword.as_ref(), "from_x").is_none());
+        let built = pkeyword::<I>("if then else".split(' '));
+        assert!(leaf(built.as_ref(), "ELSE x") == Some(("ELSE", " x")));
+        let ctx = core::Context::new();
+        assert!(keyword
+            .run(core::StrState::with_context("where", &ctx))
+            .is_err());
+        let items = ctx.expected().unwrap().items;
+        assert!(
+            items == ["`select`", "`sel`", "`FROM`"],
+            "items were: {:?}",
+            items
+        );
+    }
+    #[test]
+    fn charset_passes() {
+        use crate::charset::CharSet;
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        struct I;
+        impl core::Identifier for I {}
+        use primitives::*;
+        let word = CharSet::ascii_alphanumeric().union(&CharSet::chars(['_']));
+        assert!(word.to_string() == "[0-9A-Z_a-z]", "word was: {}", word);
+        assert!(word.contains('_') && word.contains('q') && !word.contains('-'));
+        let hex = CharSet::ascii_digit().union(&CharSet::range('a', 'f'));
+        assert!(hex.intersection(&word) == hex);
+        assert!(word.difference(&hex).to_string() == "[A-Z_g-z]");
+        let other = word.negate();
+        assert!(!other.contains('a') && other.contains('-') && other.contains('\u{10FFFF}'));
+        assert!(other.negate() == word);
+        assert!(CharSet::new().negate() == CharSet::range('\0', char::MAX));
+        assert!(other.union(&word).negate().is_empty());
+        let letters = CharSet::alphabetic();
+        assert!(letters.contains('ß') && letters.contains('Ж') && !letters.contains('1'));
+        assert!(CharSet::whitespace().contains('\u{3000}'));
+        let start = CharSet::xid_start();
+        let cont = CharSet::xid_continue();
+        assert!(start.contains('é') && !start.contains('_') && !start.contains('1'));
+        assert!(cont.contains('_') && cont.contains('\u{301}') && !start.contains('\u{301}'));
+        assert!(start.difference(&cont).is_empty());
+        // identifiers built from runtime sets
+        let ident = pset::<I>(start.union(&CharSet::chars(['_'])))
+            .seq(pset(cont).multiple())
+            .catenate();
+        match ident.run(core::StrState::new("_été2 x")) {
+            Ok((core::NonTerminal::Leaf(l, _), _)) => assert!(l == "_été2"),
+            r => panic!("r was: {:?}", r),
+        }
+        let ctx = core::Context::new();
+        assert!(pset::<I>(hex)
+            .run(core::StrState::with_context("g", &ctx))
+            .is_err());
+        let items = ctx.expected().unwrap().items;
+        assert!(
+            items == ["character in [0-9a-f]"],
+            "items were: {:?}",
+            items
+        );
+    }
+    #[test]
+    fn regex_passes() {
+        use crate::regex::Regex;
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        struct I;
+        impl core::Identifier for I {}
+        use primitives::*;
+        let prefix = |pattern: &str, s: &str| Regex::new(pattern).unwrap().prefix(s, true);
+        let ident = "[A-Za-z_][A-Za-z0-9_]*";
+        assert!(prefix(ident, "foo_1 = 2") == Some(5));
+        assert!(prefix(ident, "1foo").is_none());
+        let float = r"-?\d+(\.\d+)?([eE][+-]?\d+)?";
+        assert!(prefix(float, "-12.5e+3;") == Some(8));
+        assert!(prefix(float, "12.x") == Some(2));
+        // earlier alternatives win, and lazy repetitions take little
+        assert!(prefix("a|ab", "abc") == Some(1));
+        assert!(prefix("ab|a", "abc") == Some(2));
+        assert!(prefix("a+?", "aaa") == Some(1));
+        assert!(prefix("<.*>", "<a><b>") == Some(6));
+        assert!(prefix("<.*?>", "<a><b>") == Some(3));
+        assert!(prefix("x{2,3}", "xxxx") == Some(3));
+        assert!(prefix("x{2}", "x").is_none());
+        assert!(prefix(r#""([^"\\]|\\.)*""#, r#""a\"b" c"#) == Some(6));
+        assert!(prefix("(a*)*b", &"a".repeat(64)).is_none());
+        assert!(prefix("a$", "a") == Some(1) && prefix("a$", "ab").is_none());
+        assert!(Regex::new("^a").unwrap().prefix("a", false).is_none());
+        let error = |pattern: &str| Regex::new(pattern).unwrap_err();
+        assert!(error("(ab").offset == 0 && error("a)").offset == 1);
+        assert!(error("[b-a]").msg == "Range has its bounds reversed!");
+        assert!(error("*a").msg == "Repetition of nothing!");
+        assert!(error(r"\q").msg == "Unknown escape!");
+        // nested repetitions multiply, and are bounded as a whole
+        let nested = error("((a{1000}){1000}){1000}");
+        assert!(nested.offset == 10 && nested.msg.starts_with("Pattern is too large"));
+        assert!(error(&"a{1000}".repeat(200)).offset == 0);
+        assert!(Regex::new("(a{100}){100}").is_ok());
+        // the primitive matches at the head, and names its pattern
+        let p = pregex::<I>(ident)
+            .seq(pregex(r"\s*=\s*"))
+            .seq(pregex(r"\d+"));
+        match p.run(core::StrState::new("x1 = 42;")) {
+            Ok((_, s)) => assert!(s.head == 7),
+            Err((e, _)) => panic!("e was: {}", e),
+        }
+        let ctx = core::Context::new();
+        match p.run(core::StrState::with_context("x1 = y", &ctx)) {
+            Err((e, _)) => assert!(format!("{:?}", e.expected).contains(r"/\d+/")),
+            Ok((r, _)) => panic!("r was: {:?}", r),
+        }
+        let items = ctx.expected().unwrap().items;
+        assert!(items == [r"match of /\d+/"], "items were: {:?}", items);
+    }
+    #[test]
+    fn literals_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Assign,
+            Eq,
+            Le,
+            Lt,
+            Shl,
+        }
+        impl core::Identifier for I {}
+        use crate::core::NonTerminal::*;
+        use primitives::*;
+        let ops = pliterals::<I, _>(["=", "<", "==", "<=", "<<="]);
+        let matched = |text| match ops.run(StrState::new(text)) {
+            Ok((Leaf(l, _), _)) => Some(l),
+            Ok((r, _)) => panic!("r was: {:?}", r),
+            Err(_) => None,
+        };
+        assert!(matched("== 1") == Some("=="));
+        assert!(matched("=1") == Some("="));
+        assert!(matched("<<= 1") == Some("<<="));
+        assert!(matched("<<1") == Some("<"));
+        assert!(matched("!=").is_none());
+        let table = pliterals_labeled([
+            ("=", I::Assign),
+            ("==", I::Eq),
+            ("<", I::Lt),
+            ("<=", I::Le),
+            ("<<", I::Shl),
+        ]);
+        let expr = pchar('a').seq(table).seq(pchar('b'));
+        match expr.run(StrState::new("a<=b")) {
+            Ok((r, s)) => {
+                assert!(s.is_empty());
+                let expected = Congregate(
+                    vec![
+                        Leaf("a", ANY),
+                        Node {
+                            identifier: I::Le,
+                            children: Box::new(Leaf("<=", ANY)),
+                            span: ANY,
+                        },
+                        Leaf("b", ANY),
+                    ],
+                    ANY,
+                );
+                assert!(r == expected, "r was: {:?}", r);
+            }
+            Err((e, _)) => panic!("e was: {}", e),
+        }
+        let ctx = core::Context::new();
+        assert!(expr.run(StrState::with_context("a!b", &ctx)).is_err());
+        let items = ctx.expected().unwrap().items;
+        assert!(
+            items.len() == 5 && items[1] == "`==`",
+            "items were: {:?}",
+            items
+        );
+    }
+    #[test]
+    fn numbers_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        struct I;
+        impl core::Identifier for I {}
+        use crate::core::NonTerminal::*;
+        use number::Number;
+        let rust = Number::float().sign(true).prefixes(true).underscores(true);
+        let p = primitives::pnumber::<I>(rust);
+        let matched = |text| match p.run(StrState::new(text)) {
+            Ok((Leaf(l, _), _)) => Ok(l),
+            Ok((r, _)) => panic!("r was: {:?}", r),
+            Err((e, _)) => Err((e.head, e.msg)),
+        };
+        assert!(matched("-1_000.5e+3;") == Ok("-1_000.5e+3"));
+        assert!(matched("0xFF_ff") == Ok("0xFF_ff"));
+        assert!(matched("1..2") == Ok("1"));
+        assert!(matched("2.x") == Ok("2"));
+        assert!(matched("x") == Err((0, None)));
+        assert!(matched("") == Err((0, Some("At end of input!"))));
+        assert!(matched("1.5e;") == Err((4, Some("Digit expected after exponent!"))));
+        assert!(matched("0x;") == Err((2, Some("Hexadecimal digit expected after `0x`!"))));
+        assert!(matched("0b102") == Err((4, Some("Digit out of range for the radix!"))));
+        assert!(matched("1__0") == Err((2, Some("Digit expected after `_`!"))));
+        assert!(matched("-x") == Err((1, Some("Digit expected after sign!"))));
+        let ctx = core::Context::new();
+        assert!(p.run(StrState::with_context("0x1_", &ctx)).is_err());
+        let expected = ctx.expected().unwrap();
+        assert!(expected.column == 4 && expected.items == ["hexadecimal digit"]);
+        let integer = number::integer::<I>(Number::integer().sign(true).prefixes(true));
+        let value = |text| integer.run(StrState::new(text)).map(|(v, _)| v).ok();
+        assert!(value("-0x7f") == Some(-127));
+        assert!(value("0b101") == Some(5));
+        // without fractions in the syntax, `.5` is left over
+        assert!(integer
+            .run(StrState::new("1.5"))
+            .is_ok_and(|(v, s)| v == 1 && &*s == ".5"));
+        let (min, over) = (i128::MIN.to_string(), format!("{}0", i128::MAX));
+        assert!(value(&min) == Some(i128::MIN));
+        let msg = |p: &typed::Typed<I, i128>, text| {
+            p.run(StrState::new(text)).err().and_then(|(e, _)| e.msg)
+        };
+        assert!(msg(&integer, &over) == Some("Integer too large!"));
+        let integer = number::integer::<I>(Number::float());
+        assert!(msg(&integer, "1e5") == Some("Integer expected, not a fraction or exponent!"));
+        assert!(msg(&integer, "15").is_none());
+        let float = number::float::<I>(rust);
+        let value = |text| float.run(StrState::new(text)).map(|(v, _)| v).ok();
+        assert!(value("1_2.5e-1") == Some(1.25));
+        assert!(value("0x10") == Some(16.0));
+        let huge = format!("0x1{}", "0".repeat(40));
+        assert!(value(&huge) == Some(2f64.powi(160)));
+        assert!(value(&format!("-{}", huge)) == Some(-(2f64.powi(160))));
+        assert!(Number::integer().int_value("1_000") == Some(1000));
+    }
+    #[test]
+    fn quoted_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        struct I;
+        impl core::Identifier for I {}
+        use crate::core::NonTerminal::*;
+        use quoted::Quoted;
+        let p = primitives::pquoted::<I>(Quoted::new().quotes(['"', '\'']));
+        let matched = |text| match p.run(StrState::new(text)) {
+            Ok((Leaf(l, _), _)) => Ok(l),
+            Ok((r, _)) => panic!("r was: {:?}", r),
+            Err((e, _)) => Err((e.head, e.msg)),
+        };
+        assert!(matched(r#""a\"b" c"#) == Ok(r#""a\"b""#));
+        assert!(matched(r#"'say "hi"'"#) == Ok(r#"'say "hi"'"#));
+        assert!(matched("x") == Err((0, None)));
+        assert!(matched("") == Err((0, Some("At end of input!"))));
+        assert!(matched(r#""ab\q""#) == Err((3, Some("Unknown escape!"))));
+        assert!(matched(r#""\x4""#).is_err_and(|(at, _)| at == 1));
+        assert!(matched(r#""\u{D800}""#).is_err_and(|(at, _)| at == 1));
+        assert!(matched(r#""ab"#) == Err((0, Some("Unterminated string!"))));
+        let string = quoted::string::<I>(Quoted::new());
+        let value = |text| string.run(StrState::new(text)).map(|(v, _)| v).ok();
+        assert!(value(r#""a\n\x41\u{1F600}\\""#).as_deref() == Some("a\nA\u{1F600}\\"));
+        let plain = quoted::string::<I>(Quoted::new().no_escapes(true));
+        assert!(plain
+            .run(StrState::new(r#""C:\dir""#))
+            .is_ok_and(|(v, _)| v == r"C:\dir"));
+        assert!(matched(r#""\x+4""#).is_err_and(|(at, _)| at == 1));
+        assert!(matched(r#""\u{+41}""#).is_err_and(|(at, _)| at == 1));
+        let sql = Quoted::new().quotes(['\'']).no_escapes(true).doubled(true);
+        assert!(sql.decode("'it''s'") == "it's");
+        assert!(sql
+            .scan("'a'' b' c")
+            .is_some_and(|r| r == Ok((7, "a' b".into()))));
+    }
+    #[test]
     fn ppred_passes() {
         use std::ops::Deref;
         #[derive(PartialEq, Eq, Clone, Copy, Debug)]
         struct I;
         impl core::Identifier for I {}
         let p = primitives::ppredicate::<I, _>(|c| {
-            (['f', 'u', 'c', 'k'].contains(&c.chars().nth(0).unwrap()), 1)
+            (['f', 'u', 'c', 'k'].contains(&c.chars().next().unwrap()), 1)
         });
         let s = core::StrState::new("cukf k");
         if let Ok((r, s)) = p.run(s) {
             assert!(s.deref() == "ukf k", "s.deref() was: {}\n", s.deref());
-            assert!(r == core::NonTerminal::<I>::Leaf("c"));
+            assert!(r == core::NonTerminal::<I>::Leaf("c", ANY));
         } else {
             panic!("Parser failed!");
         }
@@ -67,7 +384,9 @@ mod tests {
         let s = core::StrState::new("cukf k");
         if let Ok((r, s)) = p.run(s) {
             assert!(s.deref() == "ukf k", "s.deref() was: {}\n", s.deref());
-            assert!(r == core::NonTerminal::<I>::Leaf("c"));
+            assert!(r == core::NonTerminal::<I>::Leaf("c", ANY));
+            // advancing by nothing is fine even at the end of input
+            assert!(s.advance(5).advance(0).is_empty());
         } else {
             panic!("Parser failed!");
         }
@@ -79,19 +398,22 @@ mod tests {
         struct I;
         impl core::Identifier for I {}
         let p = primitives::ppredicate::<I, _>(|c| {
-            (['f', 'u', 'c', 'k'].contains(&c.chars().nth(0).unwrap()), 1)
+            (['f', 'u', 'c', 'k'].contains(&c.chars().next().unwrap()), 1)
         })
         .multiple();
         let s = core::StrState::new("cukf k");
         if let Ok((r, s)) = p.run(s) {
             assert!(s.deref() == " k", "s.deref() was: {}\n", s.deref());
             assert!(
-                r == core::NonTerminal::<I>::Congregate(vec![
-                    core::NonTerminal::<I>::Leaf("c"),
-                    core::NonTerminal::<I>::Leaf("u"),
-                    core::NonTerminal::<I>::Leaf("k"),
-                    core::NonTerminal::<I>::Leaf("f"),
-                ]),
+                r == core::NonTerminal::<I>::Congregate(
+                    vec![
+                        core::NonTerminal::<I>::Leaf("c", ANY),
+                        core::NonTerminal::<I>::Leaf("u", ANY),
+                        core::NonTerminal::<I>::Leaf("k", ANY),
+                        core::NonTerminal::<I>::Leaf("f", ANY),
+                    ],
+                    ANY
+                ),
                 "r was: {:?}",
                 r
             );
@@ -106,19 +428,22 @@ mod tests {
         struct I;
         impl core::Identifier for I {}
         let p = primitives::ppredicate::<I, _>(|c| {
-            (['f', 'u', 'c', 'k'].contains(&c.chars().nth(0).unwrap()), 1)
+            (['f', 'u', 'c', 'k'].contains(&c.chars().next().unwrap()), 1)
         })
         .atleast_once();
         let s = core::StrState::new("cukf k");
         if let Ok((r, s)) = p.run(s) {
             assert!(s.deref() == " k", "s.deref() was: {}\n", s.deref());
             assert!(
-                r == core::NonTerminal::<I>::Congregate(vec![
-                    core::NonTerminal::<I>::Leaf("c"),
-                    core::NonTerminal::<I>::Leaf("u"),
-                    core::NonTerminal::<I>::Leaf("k"),
-                    core::NonTerminal::<I>::Leaf("f"),
-                ]),
+                r == core::NonTerminal::<I>::Congregate(
+                    vec![
+                        core::NonTerminal::<I>::Leaf("c", ANY),
+                        core::NonTerminal::<I>::Leaf("u", ANY),
+                        core::NonTerminal::<I>::Leaf("k", ANY),
+                        core::NonTerminal::<I>::Leaf("f", ANY),
+                    ],
+                    ANY
+                ),
                 "r was: {:?}",
                 r
             );
@@ -135,7 +460,7 @@ mod tests {
         let s = core::StrState::new("c");
         if let Ok((r, s)) = p.run(s) {
             assert!(s.is_empty());
-            assert!(r == core::NonTerminal::<I>::Leaf("c"));
+            assert!(r == core::NonTerminal::<I>::Leaf("c", ANY));
         } else {
             panic!("Parser failed!");
         }
@@ -153,12 +478,15 @@ mod tests {
         if let Ok((r, s)) = p.run(s) {
             assert!(s.is_empty());
             assert!(
-                r == core::NonTerminal::<I>::Congregate(vec![
-                    core::NonTerminal::<I>::Leaf("d"),
-                    core::NonTerminal::<I>::Leaf("a"),
-                    core::NonTerminal::<I>::Leaf("m"),
-                    core::NonTerminal::<I>::Leaf("n"),
-                ])
+                r == core::NonTerminal::<I>::Congregate(
+                    vec![
+                        core::NonTerminal::<I>::Leaf("d", ANY),
+                        core::NonTerminal::<I>::Leaf("a", ANY),
+                        core::NonTerminal::<I>::Leaf("m", ANY),
+                        core::NonTerminal::<I>::Leaf("n", ANY),
+                    ],
+                    ANY
+                )
             );
         } else {
             panic!("Error: {}", p.run(s).unwrap_err().0.info());
@@ -177,7 +505,7 @@ mod tests {
         let s = core::StrState::new("damn");
         if let Ok((r, s)) = p.run(s) {
             assert!(s.deref() == "amn");
-            assert!(r == core::NonTerminal::<I>::Leaf("d"),);
+            assert!(r == core::NonTerminal::<I>::Leaf("d", ANY),);
         } else {
             panic!("Parser failed!");
         }
@@ -195,7 +523,7 @@ mod tests {
         let s = core::StrState::new("damn");
         if let Ok((r, s)) = p.run(s) {
             assert!(s.is_empty());
-            assert!(r == core::NonTerminal::<I>::Leaf("damn"));
+            assert!(r == core::NonTerminal::<I>::Leaf("damn", ANY));
         } else {
             panic!("Parser failed!");
         }
@@ -209,7 +537,7 @@ mod tests {
         let s = core::StrState::new("c");
         if let Ok((r, s)) = p.run(s) {
             assert!(s.is_empty());
-            assert!(r == core::NonTerminal::<I>::Leaf("c"));
+            assert!(r == core::NonTerminal::<I>::Leaf("c", ANY));
         } else {
             panic!("Parser failed!");
         }
@@ -223,19 +551,14 @@ mod tests {
         let s = core::StrState::new("c");
         if let Ok((r, s)) = p.run(s) {
             assert!(s.is_empty());
-            assert!(r == core::NonTerminal::<I>::Leaf("c"));
+            assert!(r == core::NonTerminal::<I>::Leaf("c", ANY));
         } else {
             panic!("Parser failed!");
         }
     }
     #[test]
     fn combination_passes() {
-        fn log(
-            res: &Result<
-                (core::NonTerminal<'_, I>, StrState<'_>),
-                (core::ParseError<'_, I>, StrState<'_>),
-            >,
-        ) {
+        fn log(res: &core::ParseResult<'_, I>) {
             eprintln!("{:?}", res);
         }
         #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -276,4 +599,1312 @@ mod tests {
             Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
         }
     }
+    #[test]
+    fn skipping_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Num,
+            Op,
+        }
+        impl core::Identifier for I {}
+        use crate::core::NonTerminal::*;
+        use primitives::*;
+        let trivia = || {
+            ptrivia(
+                trivia::Trivia::new()
+                    .line_comment("//")
+                    .block_comment("/*", "*/"),
+            )
+        };
+        let num = || {
+            pin(['1', '2', '3', '4', '5', '6', '7', '8', '9', '0'])
+                .atleast_once()
+                .catenate()
+                .atomic()
+                .label(I::Num)
+        };
+        let op = || pchar('+').or(pchar('-')).label(I::Op);
+        let expr = || num().seq(op().seq(num()).multiple());
+        let p = expr().skipping(trivia());
+        match p.run(StrState::new(" 69 +/* c */ 420 // end\n- 9\n")) {
+            Ok((_, s)) => assert!(s.is_empty(), "s was: {:?}", s),
+            Err((e, _)) => panic!("e was: {}", e),
+        }
+        let spaced = p.run(StrState::new("1 + 2")).unwrap().0;
+        assert!(spaced == expr().run(StrState::new("1+2")).unwrap().0);
+        // digits of a number are not split by trivia
+        assert!(p
+            .run(StrState::new("6 9"))
+            .is_ok_and(|(_, s)| !s.is_empty()));
+        match p.run(StrState::new("1 + /* 2")) {
+            Err((e, _)) => assert!(e.head == 4 && e.msg == Some("Unclosed comment!")),
+            Ok((r, _)) => panic!("r was: {:?}", r),
+        }
+        let kept = expr().skipping_kept(trivia());
+        match kept.run(StrState::new("1 +2 ")) {
+            Ok((r, _)) => {
+                let expected = Congregate(
+                    vec![
+                        Node {
+                            identifier: I::Num,
+                            children: Box::new(Leaf("1", ANY)),
+                            span: ANY,
+                        },
+                        Leaf(" ", ANY),
+                        Congregate(
+                            vec![Congregate(
+                                vec![
+                                    Node {
+                                        identifier: I::Op,
+                                        children: Box::new(Leaf("+", ANY)),
+                                        span: ANY,
+                                    },
+                                    Node {
+                                        identifier: I::Num,
+                                        children: Box::new(Leaf("2", ANY)),
+                                        span: ANY,
+                                    },
+                                ],
+                                ANY,
+                            )],
+                            ANY,
+                        ),
+                    ],
+                    ANY,
+                );
+                assert!(
+                    r == Congregate(vec![expected, Leaf(" ", ANY)], ANY),
+                    "r was: {:?}",
+                    r
+                );
+            }
+            Err((e, _)) => panic!("e was: {}", e),
+        }
+        let ctx = core::Context::new();
+        assert!(p.run(StrState::with_context("1 + x", &ctx)).is_ok());
+        let expected = ctx.expected().unwrap();
+        assert!(expected.column == 4, "expected was: {:?}", expected);
+    }
+    #[test]
+    fn indent_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Stmt,
+            Block,
+        }
+        impl core::Identifier for I {}
+        use indent::{dedent, indent, same_indent};
+        use primitives::*;
+        let name = || {
+            ppredicate(|s: &str| {
+                let l = s.chars().take_while(|c| c.is_ascii_lowercase()).count();
+                (l > 0, l)
+            })
+        };
+        let stmt = rule::Rule::new();
+        let block = || {
+            stmt.parser()
+                .seq(same_indent().seq(stmt.parser()).multiple())
+                .label(I::Block)
+        };
+        stmt.define(
+            name()
+                .seq(pchar(':'))
+                .seq(pchar('\n'))
+                .seq(indent(block()))
+                .or(name().seq(pchar('\n').or(dedent())))
+                .label(I::Stmt),
+        );
+        let file = block().seq(dedent());
+        let blocks = |text| match file.run(StrState::new(text)) {
+            Ok((r, s)) => {
+                assert!(s.is_empty(), "s was: {:?}", s);
+                fn count(n: &core::NonTerminal<'_, I>) -> usize {
+                    match n {
+                        core::NonTerminal::Node {
+                            identifier,
+                            children,
+                            ..
+                        } => (*identifier == I::Block) as usize + count(children),
+                        core::NonTerminal::Congregate(v, _) => v.iter().map(count).sum(),
+                        _ => 0,
+                    }
+                }
+                Ok(count(&r))
+            }
+            Err((e, _)) => Err((e.location.1, e.msg)),
+        };
+        let text = "a:\n  b\n\n  c:\n    d\n  e\nf:\n\tg\n";
+        assert!(blocks(text) == Ok(4), "blocks were: {:?}", blocks(text));
+        // the inner block ends at the end of input, wherever it left off
+        assert!(blocks("a:\n  b:\n    c") == Ok(3));
+        // ` c` is deeper than `a` but not as deep as the block of `b`
+        assert!(blocks("a:\n  b:\n c\n").is_err());
+        assert!(blocks("a:\n  b\n   c\n") == Err((2, None)));
+        let nested = || pstr("x\n").seq(indent(pstr("y\n")));
+        let p = indent::<I>(
+            nested()
+                .seq(pchar('!'))
+                .or(nested().seq(same_indent()).seq(pchar('z'))),
+        );
+        // the level of the block of `y` is gone after backtracking
+        assert!(p.run(StrState::new("  x\n    y\n  z")).is_ok());
+        assert!(blocks("a:\n \tb\n") == Err((1, Some("Mixed tabs and spaces in indentation!"))));
+        assert!(
+            blocks("a:\n  b:\n\t\tc\n")
+                == Err((2, Some("Indentation mixes tabs and spaces with its block!")))
+        );
+    }
+    #[test]
+    fn pratt_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Num,
+            Neg,
+            Sub,
+            Mul,
+            Pow,
+            Fact,
+            Lt,
+        }
+        impl core::Identifier for I {}
+        use crate::core::NonTerminal::{self, *};
+        use pratt::{pratt, Assoc, Operators};
+        use primitives::*;
+        let num = pin(['1', '2', '3', '4', '5', '6', '7', '8', '9', '0']).label(I::Num);
+        let ops = Operators::new()
+            .prefix(pchar('-'), I::Neg, 4)
+            .infix(pchar('<'), I::Lt, 1, Assoc::None)
+            .infix(pchar('-'), I::Sub, 2, Assoc::Left)
+            .infix(pchar('*'), I::Mul, 3, Assoc::Left)
+            .infix(pchar('^'), I::Pow, 5, Assoc::Right)
+            .postfix(pchar('!'), I::Fact, 6);
+        let expr = pratt(num, ops).skipping(pchar(' ').multiple());
+        // the tree as a term, such as `(- 1 2)`
+        fn term(n: &NonTerminal<'_, I>) -> String {
+            match n {
+                Node {
+                    identifier: I::Num,
+                    children,
+                    ..
+                } => term(children),
+                Node {
+                    identifier,
+                    children,
+                    ..
+                } => format!("({:?} {})", identifier, term(children)),
+                Congregate(v, _) => v
+                    .iter()
+                    .filter(|n| !matches!(n, Leaf(..)))
+                    .map(term)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                Leaf(l, _) => l.to_string(),
+                _ => String::new(),
+            }
+        }
+        let parsed = |text| match expr.run(StrState::new(text)) {
+            Ok((r, s)) => Ok((term(&r), s.len())),
+            Err((e, _)) => Err((e.head, e.msg)),
+        };
+        let cases = [
+            ("1 - 2 - 3", "(Sub (Sub 1 2) 3)"),
+            ("1 - 2 * 3 ^ 4 ^ 5", "(Sub 1 (Mul 2 (Pow 3 (Pow 4 5))))"),
+            ("-1 * 2", "(Mul (Neg 1) 2)"),
+            ("-2 ^ 2", "(Neg (Pow 2 2))"),
+            ("-3! - -1", "(Sub (Neg (Fact 3)) (Neg 1))"),
+            ("1 < 2 - 3", "(Lt 1 (Sub 2 3))"),
+        ];
+        for (text, expected) in cases {
+            let r = parsed(text);
+            assert!(r == Ok((expected.to_owned(), 0)), "{} was: {:?}", text, r);
+        }
+        // an operator without an operand is left over
+        assert!(parsed("1 - 2 *") == Ok(("(Sub 1 2)".to_owned(), 1)));
+        assert!(parsed("1 < 2 < 3") == Err((6, Some("Operator is not associative!"))));
+        match expr.run(StrState::new("1-2")) {
+            Ok((Node { span, .. }, _)) => assert!(span.len() == 3),
+            r => panic!("r was: {:?}", r),
+        }
+    }
+    #[test]
+    fn sep_by_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        struct I;
+        impl core::Identifier for I {}
+        use crate::core::NonTerminal::*;
+        use combinators::{delimited, Trailing};
+        use primitives::*;
+        let digit = || pin(['1', '2', '3', '4', '5', '6', '7', '8', '9', '0']);
+        let list = |trailing, least| {
+            let items = match least {
+                0 => digit().sep_by(pchar(','), trailing),
+                _ => digit().sep_by1(pchar(','), trailing),
+            };
+            move |text| match items.run(StrState::new(text)) {
+                Ok((r, s)) => Some((
+                    match r {
+                        Congregate(v, _) => v.len(),
+                        _ => 0,
+                    },
+                    s.len(),
+                )),
+                Err(_) => None,
+            }
+        };
+        let allow = list(Trailing::Allow, 0);
+        assert!(allow("1,2,3;") == Some((5, 1)));
+        assert!(allow("1,2,;") == Some((4, 1)));
+        assert!(allow(";") == Some((0, 1)));
+        let forbid = list(Trailing::Forbid, 1);
+        assert!(forbid("1,2,;") == Some((3, 2)));
+        assert!(forbid(";").is_none());
+        let require = list(Trailing::Require, 1);
+        assert!(require("1,2,3") == Some((4, 1)));
+        assert!(require("1;").is_none());
+        let call = delimited(
+            pchar::<I>('('),
+            digit().sep_by(pchar(','), Trailing::Allow),
+            pchar(')'),
+        )
+        .skipping(pchar(' ').multiple());
+        match call.run(StrState::new("( 1, 2 )")) {
+            Ok((r, _)) => {
+                let expected = Congregate(
+                    vec![
+                        Leaf("(", ANY),
+                        Leaf("1", ANY),
+                        Leaf(",", ANY),
+                        Leaf("2", ANY),
+                        Leaf(")", ANY),
+                    ],
+                    ANY,
+                );
+                assert!(r == expected, "r was: {:?}", r);
+            }
+            Err((e, _)) => panic!("e was: {}", e),
+        }
+        assert!(call.run(StrState::new("()")).is_ok());
+        let nested = pchar('x').seq(call);
+        match nested.run(StrState::new("x(1,\n 2")) {
+            Err((e, _)) => assert!(e.head == 1 && e.msg == Some("Unclosed delimiter!")),
+            Ok((r, _)) => panic!("r was: {:?}", r),
+        }
+    }
+    #[test]
+    fn memo_passes() {
+        use std::cell::Cell;
+        use std::ops::Deref;
+        use std::rc::Rc;
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        struct I;
+        impl core::Identifier for I {}
+        use primitives::*;
+        let runs = Rc::new(Cell::new(0));
+        let counter = runs.clone();
+        let digits = ppredicate::<I, _>(move |s| {
+            counter.set(counter.get() + 1);
+            let l = s.chars().take_while(|c| c.is_ascii_digit()).count();
+            (l > 0, l)
+        })
+        .memo();
+        let ctx = core::Context::new();
+        let s = StrState::with_context("12-", &ctx);
+        for _ in 0..2 {
+            match digits.run(s) {
+                Ok((r, s)) => {
+                    assert!(s.deref() == "-");
+                    assert!(r == core::NonTerminal::<I>::Leaf("12", ANY));
+                }
+                Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
+            }
+        }
+        assert!(runs.get() == 1, "runs was: {}", runs.get());
+        assert!(
+            ctx.memo_stats()
+                == memo::MemoStats {
+                    hits: 1,
+                    misses: 1,
+                    released: 0
+                },
+            "stats were: {:?}",
+            ctx.memo_stats()
+        );
+        assert!(digits.run(StrState::new("12-")).is_ok());
+        assert!(runs.get() == 2, "runs was: {}", runs.get());
+    }
+    #[test]
+    fn packrat_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        struct I;
+        impl core::Identifier for I {}
+        use primitives::*;
+        let p = pchar::<I>('a')
+            .seq(pchar('b'))
+            .seq(pchar('c'))
+            .or(pchar('a').seq(pchar('b')))
+            .multiple();
+        let ctx = core::Context::packrat();
+        let s = StrState::with_context("ababc", &ctx);
+        let memoized = p.run(s);
+        let plain = p.run(StrState::new("ababc"));
+        match (memoized, plain) {
+            (Ok((m, ms)), Ok((n, ns))) => {
+                assert!(m == n, "m was: {:?}", m);
+                assert!(ms.is_empty() && ns.is_empty());
+            }
+            _ => panic!("Parser failed!"),
+        }
+        assert!(ctx.memo_stats().misses > 0);
+        // parsers without fields are not mistaken for each other
+        let p = pany::<I>().ensure().seq(indent::dedent());
+        let ctx = core::Context::packrat();
+        assert!(p.run(StrState::with_context("x\n", &ctx)).is_err());
+        // nor is a result in one block or with trivia for one outside
+        let line = rule::Rule::<I>::new();
+        line.define(indent::same_indent().seq(pchar('y')));
+        let p = indent::indent(pstr("x\n").seq(line.parser()).seq(pchar('!')))
+            .or(pstr("  x\n").seq(line.parser()));
+        let ctx = core::Context::new();
+        assert!(p.run(StrState::with_context("  x\n  y", &ctx)).is_err());
+        let ab = rule::Rule::<I>::new();
+        ab.define(pchar('a').seq(pchar('b')));
+        let p = ab
+            .parser()
+            .seq(pchar('!'))
+            .skipping(pchar(' '))
+            .or(ab.parser());
+        let ctx = core::Context::new();
+        assert!(p.run(StrState::with_context("a b", &ctx)).is_err());
+    }
+    #[test]
+    fn rule_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Expr,
+            Num,
+        }
+        impl core::Identifier for I {}
+        use primitives::*;
+        let expr = rule::Rule::<I>::new();
+        expr.define(
+            pchar('(')
+                .seq(expr.parser())
+                .seq(pchar(')'))
+                .or(pin(['1', '2', '3']).atleast_once().catenate().label(I::Num))
+                .label(I::Expr),
+        );
+        let p = expr.parser();
+        let s = StrState::new("((12))");
+        if let Ok((r, s)) = p.run(s) {
+            assert!(s.is_empty());
+            let num = core::NonTerminal::Node {
+                identifier: I::Expr,
+                children: Box::new(core::NonTerminal::Node {
+                    identifier: I::Num,
+                    children: Box::new(core::NonTerminal::Leaf("12", ANY)),
+                    span: ANY,
+                }),
+                span: ANY,
+            };
+            let wrap = |n| core::NonTerminal::Node {
+                identifier: I::Expr,
+                children: Box::new(core::NonTerminal::Congregate(
+                    vec![
+                        core::NonTerminal::Leaf("(", ANY),
+                        n,
+                        core::NonTerminal::Leaf(")", ANY),
+                    ],
+                    ANY,
+                )),
+                span: ANY,
+            };
+            assert!(r == wrap(wrap(num)), "r was: {:?}", r);
+        } else {
+            panic!("Parser failed!");
+        }
+        assert!(p.run(StrState::new("((12)")).is_err());
+    }
+    #[test]
+    fn left_recursion_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Sum,
+            Diff,
+        }
+        impl core::Identifier for I {}
+        use crate::core::NonTerminal::*;
+        use primitives::*;
+        // sum := sum '+' num / num
+        let sum = rule::Rule::<I>::new();
+        sum.define(
+            sum.parser()
+                .seq(pchar('+'))
+                .seq(pin(['1', '2', '3']))
+                .or(pin(['1', '2', '3']))
+                .label(I::Sum),
+        );
+        // diff := term '-' num / num ; term := diff
+        let diff = rule::Rule::<I>::new();
+        let term = rule::Rule::<I>::new();
+        diff.define(
+            term.parser()
+                .seq(pchar('-'))
+                .seq(pin(['1', '2', '3']))
+                .or(pin(['1', '2', '3']))
+                .label(I::Diff),
+        );
+        term.define(diff.parser());
+        let node = |identifier, children| Node {
+            identifier,
+            children: Box::new(children),
+            span: ANY,
+        };
+        for ctx in [core::Context::new(), core::Context::packrat()] {
+            let p = sum.parser();
+            match p.run(StrState::with_context("1+2+3", &ctx)) {
+                Ok((r, s)) => {
+                    assert!(s.is_empty());
+                    let one = node(I::Sum, Leaf("1", ANY));
+                    let two = node(
+                        I::Sum,
+                        Congregate(vec![one, Leaf("+", ANY), Leaf("2", ANY)], ANY),
+                    );
+                    let three = node(
+                        I::Sum,
+                        Congregate(vec![two, Leaf("+", ANY), Leaf("3", ANY)], ANY),
+                    );
+                    assert!(r == three, "r was: {:?}", r);
+                }
+                Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
+            }
+            let p = diff.parser();
+            match p.run(StrState::with_context("3-2-1", &ctx)) {
+                Ok((r, s)) => {
+                    assert!(s.is_empty());
+                    let three = node(I::Diff, Leaf("3", ANY));
+                    let two = node(
+                        I::Diff,
+                        Congregate(vec![three, Leaf("-", ANY), Leaf("2", ANY)], ANY),
+                    );
+                    let one = node(
+                        I::Diff,
+                        Congregate(vec![two, Leaf("-", ANY), Leaf("1", ANY)], ANY),
+                    );
+                    assert!(r == one, "r was: {:?}", r);
+                }
+                Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
+            }
+        }
+    }
+    #[test]
+    fn peg_passes() {
+        use std::ops::Deref;
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Num,
+            Sum,
+        }
+        impl core::Identifier for I {}
+        use crate::core::NonTerminal::*;
+        let peg = peg::Peg::new();
+        let labels = |name: &str| match name {
+            "num" => Some(I::Num),
+            "sum" => Some(I::Sum),
+            _ => None,
+        };
+        let grammar = match peg.compile(
+            "sum <- num ('+' num)* ; num <- [0-9]+ # digits\n\t!'x'",
+            labels,
+        ) {
+            Ok(g) => g,
+            Err(e) => panic!("Compilation Failed\n[Error]:\n{}", e.info()),
+        };
+        let p = grammar.start();
+        let s = StrState::new("1+23 k");
+        if let Ok((r, s)) = p.run(s) {
+            assert!(s.deref() == " k", "s.deref() was: {}\n", s.deref());
+            let num = |s| Node {
+                identifier: I::Num,
+                children: Box::new(Congregate(vec![Congregate(s, ANY), Empty(ANY)], ANY)),
+                span: ANY,
+            };
+            let expected = Node {
+                identifier: I::Sum,
+                children: Box::new(Congregate(
+                    vec![
+                        num(vec![Leaf("1", ANY)]),
+                        Congregate(
+                            vec![Congregate(
+                                vec![Leaf("+", ANY), num(vec![Leaf("2", ANY), Leaf("3", ANY)])],
+                                ANY,
+                            )],
+                            ANY,
+                        ),
+                    ],
+                    ANY,
+                )),
+                span: ANY,
+            };
+            assert!(r == expected, "r was: {:?}", r);
+        } else {
+            panic!("Parser failed!");
+        }
+        match p.run(StrState::new("1+23x")) {
+            Ok((_, s)) => assert!(s.deref() == "+23x", "s.deref() was: {}\n", s.deref()),
+            Err(_) => panic!("Parser failed!"),
+        }
+        match peg.compile::<I>("num <- [0-9]+\nsum <- num ('+' nmu)*", labels) {
+            Err(e) => assert!(e.location.1 == 1 && e.location.2 == 16, "e was: {}", e),
+            Ok(_) => panic!("Undefined rule was accepted!"),
+        }
+        match peg.compile::<I>("num <- [0-9]+\nsum <- num ('+' num*", labels) {
+            Err(e) => {
+                assert!(e.location.1 == 1 && e.location.2 == 20, "e was: {}", e);
+                let expected = e.expected().unwrap();
+                assert!(expected.items.contains(&"`)`".to_owned()), "e was: {}", e);
+            }
+            Ok(_) => panic!("Syntax error was accepted!"),
+        }
+        match peg.compile::<I>("num <- [0-9]+ ; sum <- (num", labels) {
+            Err(e) => assert!(e.location.2 == 27, "e was: {}", e),
+            Ok(_) => panic!("Syntax error was accepted!"),
+        }
+        match peg.compile::<I>("num <- [z-a]", labels) {
+            Err(e) => assert!(e.msg == Some("Reversed class range!") && e.location.2 == 8),
+            Ok(_) => panic!("Reversed range was accepted!"),
+        }
+    }
+    #[test]
+    fn grammar_passes() {
+        use std::ops::Deref;
+        grammar! {
+            enum G;
+            sum <- num ("+" num)* ;
+            num <- ['0'-'9']+ !'x' ;
+            not_sum <- !sum . ;
+        }
+        use crate::core::NonTerminal::*;
+        let p = G::Sum.parser();
+        let s = StrState::new("1+23 k");
+        if let Ok((r, s)) = p.run(s) {
+            assert!(s.deref() == " k", "s.deref() was: {}\n", s.deref());
+            let num = |s| Node {
+                identifier: G::Num,
+                children: Box::new(Congregate(vec![Congregate(s, ANY), Empty(ANY)], ANY)),
+                span: ANY,
+            };
+            let expected = Node {
+                identifier: G::Sum,
+                children: Box::new(Congregate(
+                    vec![
+                        num(vec![Leaf("1", ANY)]),
+                        Congregate(
+                            vec![Congregate(
+                                vec![Leaf("+", ANY), num(vec![Leaf("2", ANY), Leaf("3", ANY)])],
+                                ANY,
+                            )],
+                            ANY,
+                        ),
+                    ],
+                    ANY,
+                )),
+                span: ANY,
+            };
+            assert!(r == expected, "r was: {:?}", r);
+        } else {
+            panic!("Parser failed!");
+        }
+        assert!(G::NotSum.parser().run(StrState::new("1")).is_err());
+        assert!(G::NotSum.parser().run(StrState::new("k")).is_ok());
+        // the grammar is only built once
+        assert!(format!("{:?}", G::Num.parser()) == format!("{:?}", G::Num.parser()));
+    }
+    #[test]
+    fn typed_passes() {
+        use std::ops::Deref;
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        struct I;
+        impl core::Identifier for I {}
+        use primitives::*;
+        use typed::Semantic;
+        let num = || {
+            pin(['1', '2', '3', '4', '5', '6', '7', '8', '9', '0'])
+                .atleast_once()
+                .catenate()
+                .and_then(|n| {
+                    typed::leaf(&n)
+                        .parse::<i64>()
+                        .map_err(|_| "Number too large!")
+                })
+        };
+        let p = num()
+            .seq(
+                pchar::<I>('+')
+                    .or(pchar('-'))
+                    .map(|n| typed::leaf(&n) == "-")
+                    .seq(num())
+                    .multiple(),
+            )
+            .map(|(first, rest)| {
+                rest.into_iter()
+                    .fold(first, |acc, (neg, n)| if neg { acc - n } else { acc + n })
+            });
+        let s = StrState::new("69+420-9 k");
+        match p.run(s) {
+            Ok((r, s)) => {
+                assert!(s.deref() == " k", "s.deref() was: {}\n", s.deref());
+                assert!(r == 480, "r was: {}", r);
+            }
+            Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
+        }
+        match p.run(StrState::new("99999999999999999999")) {
+            Err((e, _)) => assert!(e.msg == Some("Number too large!")),
+            Ok((r, _)) => panic!("r was: {}", r),
+        }
+        let count = pchar::<I>('a').fold(|| 0, |n, _| n + 1);
+        assert!(matches!(count.run(StrState::new("aaab")), Ok((3, _))));
+        // a recursive typed grammar, skipping spaces
+        let expr = typed::TypedRule::<I, i64>::new();
+        let term = num().atomic().or(pchar('(')
+            .map(|_| ())
+            .seq(expr.parser())
+            .seq(pchar(')').map(|_| ()))
+            .map(|((_, n), _)| n));
+        expr.define(
+            term.seq(pchar('+').map(|_| ()).seq(expr.parser()).maybe())
+                .map(|(n, rest)| n + rest.map_or(0, |(_, m)| m)),
+        );
+        let p = expr.parser().skipping(pchar(' ').atleast_once());
+        match p.run(StrState::new(" 1 + ( 2+ 30 ) + 4 k")) {
+            Ok((r, s)) => {
+                assert!(s.deref() == "k", "s.deref() was: {}\n", s.deref());
+                assert!(r == 37, "r was: {}", r);
+            }
+            Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
+        }
+        // `atomic()` keeps the digits of a number together
+        assert!(matches!(p.run(StrState::new("1 2")), Ok((1, _))));
+    }
+    #[test]
+    fn input_passes() {
+        use crate::core::NonTerminal::*;
+        use crate::core::{ByteState, TokenState};
+        use std::ops::Deref;
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Method,
+            Sum,
+        }
+        impl core::Identifier for I {}
+        use primitives::*;
+        let p = ptag::<I, [u8]>(b"GET")
+            .or(ptag(b"PUT"))
+            .label(I::Method)
+            .seq(pitem(|b: &u8| *b == b' ').ignore())
+            .seq(pitem(u8::is_ascii_alphanumeric).atleast_once().catenate());
+        let s = ByteState::new(b"PUT abc1\r\n");
+        match p.run(s) {
+            Ok((r, s)) => {
+                assert!(s.deref() == b"\r\n", "s.deref() was: {:?}\n", s.deref());
+                let expected = Congregate(
+                    vec![
+                        Node {
+                            identifier: I::Method,
+                            children: Box::new(Leaf(&b"PUT"[..], ANY)),
+                            span: ANY,
+                        },
+                        Empty(ANY),
+                        Leaf(&b"abc1"[..], ANY),
+                    ],
+                    ANY,
+                );
+                assert!(r == expected, "r was: {:?}", r);
+            }
+            Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
+        }
+        assert!(p.run(ByteState::new(b"POST x")).is_err());
+
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum Tok {
+            Num(i64),
+            Plus,
+        }
+        impl core::Token for Tok {}
+        let num = || pitem::<I, [Tok], _>(|t| matches!(t, Tok::Num(_)));
+        let sum = num()
+            .seq(ptag(&[Tok::Plus][..]).seq(num()).multiple())
+            .label(I::Sum);
+        let tokens = [Tok::Num(1), Tok::Plus, Tok::Num(2), Tok::Plus];
+        match sum.run(TokenState::new(&tokens)) {
+            Ok((_, s)) => assert!(s.deref() == [Tok::Plus], "s.deref() was: {:?}\n", s.deref()),
+            Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
+        }
+        let chars: Vec<char> = "ab".chars().collect();
+        let p = pitem::<I, [char], _>(char::is_ascii_lowercase)
+            .multiple()
+            .catenate();
+        match p.run(TokenState::new(&chars)) {
+            Ok((Leaf(l, _), s)) => assert!(l == &chars[..] && s.is_empty()),
+            r => panic!("r was: {:?}", r),
+        }
+    }
+    #[test]
+    fn stream_passes() {
+        use crate::core::NonTerminal::*;
+        use std::io::Read;
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Key,
+            Value,
+        }
+        impl core::Identifier for I {}
+        use primitives::*;
+        // "key=1\n" over and over, generated as it is read
+        struct Log(usize);
+        impl Read for Log {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let mut n = 0;
+                while self.0 > 0 && n + 6 <= buf.len() {
+                    buf[n..n + 6].copy_from_slice(b"key=1\n");
+                    n += 6;
+                    self.0 -= 1;
+                }
+                Ok(n)
+            }
+        }
+        let record = pin(['a', 'e', 'k', 'y'])
+            .atleast_once()
+            .catenate()
+            .label(I::Key)
+            .seq(pchar('=').ignore())
+            .seq(pin(['0', '1']).atleast_once().catenate().label(I::Value))
+            .seq(pchar('\n').ignore());
+        let mut stream = stream::Stream::new(Log(100_000)).chunk(60);
+        let expected = Congregate(
+            vec![
+                Node {
+                    identifier: I::Key,
+                    children: Box::new(Leaf("key", ANY)),
+                    span: ANY,
+                },
+                Empty(ANY),
+                Node {
+                    identifier: I::Value,
+                    children: Box::new(Leaf("1", ANY)),
+                    span: ANY,
+                },
+                Empty(ANY),
+            ],
+            ANY,
+        );
+        let res = stream.records(record.as_ref(), |r| {
+            assert!(r == expected, "r was: {:?}", r);
+        });
+        assert!(matches!(res, Ok(100_000)), "res was: {:?}", res);
+        assert!(stream.buffered() < 200);
+
+        let text = "a=1\nkey=10\nk=2\n".as_bytes();
+        let mut stream = stream::Stream::new(text).chunk(3);
+        let mut keys = vec![];
+        let res = stream.records(record.as_ref(), |r| keys.push(format!("{:?}", r)));
+        assert!(keys.len() == 2);
+        match res {
+            Err(stream::StreamError::Parse { line, column, .. }) => {
+                assert!(line == 2 && column == 2, "error was at {}:{}", line, column)
+            }
+            r => panic!("res was: {:?}", r),
+        }
+
+        // records on a single line, and one longer than many chunks
+        let item = pin::<2, I>(['a', 'b'])
+            .atleast_once()
+            .catenate()
+            .seq(pchar(';'));
+        let text = "ab;".repeat(100_000);
+        let mut stream = stream::Stream::new(text.as_bytes()).chunk(60);
+        let res = stream.records(item.as_ref(), |_| {});
+        assert!(matches!(res, Ok(100_000)), "res was: {:?}", res);
+        assert!(stream.buffered() < 200);
+        let text = format!("{};ab;b", "a".repeat(10_000));
+        let mut stream = stream::Stream::new(text.as_bytes()).chunk(7);
+        let mut lengths = vec![];
+        let res = stream.records(item.as_ref(), |r| lengths.push(r.span().len()));
+        assert!(lengths == [10_001, 3], "lengths were: {:?}", lengths);
+        match res {
+            Err(
+                e @ stream::StreamError::Parse {
+                    line: 0,
+                    column: 10_005,
+                    ..
+                },
+            ) => {
+                assert!(e.to_string().starts_with("[0:10005]\t"), "e was: {}", e)
+            }
+            r => panic!("res was: {:?}", r),
+        }
+        // records ending in a predicate, whatever the chunks cut them into
+        let digits = || {
+            ppredicate::<I, _>(|s: &str| {
+                let l = s.chars().take_while(|c| c.is_ascii_digit()).count();
+                (l > 0, l)
+            })
+        };
+        let item = pchar('k').seq(digits());
+        for chunk in 1..8 {
+            let mut stream = stream::Stream::new("k12k345k6".as_bytes()).chunk(chunk);
+            let res = stream.records(item.as_ref(), |_| {});
+            assert!(matches!(res, Ok(3)), "res was: {:?}", res);
+        }
+    }
+    #[test]
+    fn incremental_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Sum,
+            Line,
+        }
+        impl core::Identifier for I {}
+        use primitives::*;
+        // sum := sum '+' num / num ; doc := (sum '\n')*
+        let num = || {
+            pin(['0', '1', '2', '3', '4', '5', '6', '7', '8', '9'])
+                .atleast_once()
+                .catenate()
+        };
+        let sum = rule::Rule::<I>::new();
+        sum.define(
+            sum.parser()
+                .seq(pchar('+'))
+                .seq(num())
+                .or(num())
+                .label(I::Sum),
+        );
+        let doc = sum
+            .parser()
+            .seq(pchar('\n'))
+            .label(I::Line)
+            .multiple()
+            .seq(pany().avoid());
+        let fresh = |text: &str| {
+            let ctx = core::Context::packrat();
+            match doc.run(StrState::with_context(text, &ctx)) {
+                Ok((r, s)) => Ok((format!("{:?}", r), (s.line, s.column))),
+                Err((e, _)) => Err(e.to_string()),
+            }
+        };
+        let mut ctx = core::Context::packrat();
+        let mut text = "1+2\n30+4+5\n6\n".to_owned();
+        assert!(doc.run(StrState::with_context(&text, &ctx)).is_ok());
+        let edits: [&[(std::ops::Range<usize>, &str)]; 5] = [
+            &[(6..7, "99")],
+            &[(0..1, "7+8")],
+            &[(4..4, "12+\n")],
+            &[(0..4, ""), (3..4, "+")],
+            &[(2..3, "x")],
+        ];
+        for edit in edits {
+            text = ctx.edit(&text, edit);
+            let before = ctx.memo_stats();
+            let res = match doc.run(StrState::with_context(&text, &ctx)) {
+                Ok((r, s)) => Ok((format!("{:?}", r), (s.line, s.column))),
+                Err((e, _)) => Err(e.to_string()),
+            };
+            assert!(
+                res == fresh(&text),
+                "{:?} after {:?} gave {:?}",
+                text,
+                edit,
+                res
+            );
+            assert!(
+                ctx.memo_stats().hits > before.hits,
+                "nothing reused in {:?}",
+                text
+            );
+        }
+        assert!(text == "12x+2\n30994+5\n6\n", "text was: {:?}", text);
+        assert!(fresh(&text).is_err());
+        // text inserted right after a predicate's match extends it
+        let word = ppredicate::<I, _>(|s: &str| {
+            let l = s.chars().take_while(|c| c.is_ascii_lowercase()).count();
+            (l > 0, l)
+        });
+        let p = word.seq(pchar(' ')).seq(pchar('c'));
+        let mut ctx = core::Context::packrat();
+        assert!(p.run(StrState::with_context("ab c", &ctx)).is_ok());
+        let text = ctx.edit("ab c", &[(2..2, "x")]);
+        assert!(p
+            .run(StrState::with_context(&text, &ctx))
+            .is_ok_and(|(r, _)| format!("{:?}", r).contains("\"abx\"")));
+        // joining the lines leaves `y` where a line of the block cannot start
+        let line = rule::Rule::<I>::new();
+        line.define(indent::same_indent().seq(pchar('y')));
+        let p = pchar('\n').or(pchar('x')).seq(line.parser());
+        let mut ctx = core::Context::packrat();
+        assert!(p.run(StrState::with_context("\ny", &ctx)).is_ok());
+        let text = ctx.edit("\ny", &[(0..1, "x")]);
+        assert!(p.run(StrState::with_context(&text, &ctx)).is_err());
+    }
+    #[test]
+    fn span_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Word,
+            Line,
+        }
+        impl core::Identifier for I {}
+        use crate::core::NonTerminal::*;
+        use crate::core::Span;
+        use primitives::*;
+        let word = || {
+            pin(['a', 'b', 'é'])
+                .atleast_once()
+                .catenate()
+                .label(I::Word)
+        };
+        let line = word()
+            .seq(pchar(' ').seq(word()).multiple())
+            .seq(pchar('\n'))
+            .label(I::Line);
+        let doc = line.multiple();
+        let text = "ab\nbé a\n";
+        let fresh = doc.run(StrState::new(text));
+        let ctx = core::Context::packrat();
+        let memoized = doc.run(StrState::with_context(text, &ctx));
+        let Ok((r, s)) = fresh else {
+            panic!("Parser failed!")
+        };
+        assert!(s.is_empty());
+        assert!(format!("{:?}", memoized.map(|(r, _)| r).ok()) == format!("{:?}", Some(&r)));
+        let Congregate(lines, all) = &r else {
+            panic!("r was: {:?}", r)
+        };
+        assert!(all.slice(text) == text && (all.end_line, all.end_column) == (2, 0));
+        let second = lines[1].span();
+        assert!(
+            second
+                == Span {
+                    start: 3,
+                    end: 9,
+                    line: 1,
+                    column: 0,
+                    end_line: 2,
+                    end_column: 0,
+                }
+        );
+        let Node { children, .. } = &lines[1] else {
+            panic!("r was: {:?}", r)
+        };
+        let Congregate(parts, _) = children.as_ref() else {
+            panic!("r was: {:?}", r)
+        };
+        let first = parts[0].span();
+        assert!(first.slice(text) == "bé" && (first.line, first.column) == (1, 0));
+        assert!((first.end, first.end_column) == (6, 2));
+        let last = parts[1].span();
+        assert!(last.slice(text) == " a" && last.column == 2);
+        let both = last.merge(first);
+        assert!(both.slice(text) == "bé a" && (both.column, both.end_column) == (0, 4));
+        assert!(both.contains(3) && both.contains(7) && !both.contains(8));
+        // spans do not take part in comparisons
+        assert!(Leaf::<I>("a", first) == Leaf("a", last));
+        assert!(Leaf::<I>("a", first) != Leaf("b", first));
+    }
+    #[test]
+    fn diagnostic_passes() {
+        use crate::core::Span;
+        use crate::diagnostic::Diagnostic;
+        let text = "let a =\n\tfoo(2, 3\n漢字 x\n\n\nend\n";
+        let at = |line, column, end_column| Span {
+            line,
+            column,
+            end_line: line,
+            end_column,
+            ..Span::default()
+        };
+        let d = Diagnostic::new("unclosed call")
+            .primary(at(1, 8, 8), "expected `)`")
+            .secondary(at(1, 1, 4), "this call")
+            .secondary(at(2, 3, 4), "wide")
+            .secondary(at(5, 0, 3), "")
+            .note("calls take any number of arguments")
+            .help("add `)`");
+        let plain = d.render(text);
+        let expected = "\
+error: unclosed call
+ --> 2:9
+  |
+1 | let a =
+2 |     foo(2, 3
+  |     --- this call
+  |            ^ expected `)`
+3 | 漢字 x
+  |      - wide
+4 |
+5 |
+6 | end
+  | ---
+  |
+  = note: calls take any number of arguments
+  = help: add `)`
+";
+        assert!(plain == expected, "rendered:\n{}", plain);
+        let tight = d.clone().context(0).render(text);
+        let lines: Vec<&str> = tight.lines().collect();
+        assert!(
+            lines[3..8]
+                == [
+                    "2 |     foo(2, 3",
+                    "  |     --- this call",
+                    "  |            ^ expected `)`",
+                    "3 | 漢字 x",
+                    "  |      - wide"
+                ]
+        );
+        assert!(
+            lines[8..11] == ["...", "6 | end", "  | ---"],
+            "lines were: {:?}",
+            lines
+        );
+        // colours only add escapes around the same text
+        let colored = d.color(true).render(text);
+        assert!(colored.contains("\x1b[1;31m^ expected `)`\x1b[0m"));
+        let mut stripped = String::new();
+        let mut rest = colored.as_str();
+        while let Some(i) = rest.find('\x1b') {
+            stripped += &rest[..i];
+            rest = &rest[i + rest[i..].find('m').unwrap() + 1..];
+        }
+        stripped += rest;
+        assert!(stripped == plain, "stripped:\n{}", stripped);
+        // parse errors point at where they failed and at what was expected
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        struct I;
+        impl core::Identifier for I {}
+        use primitives::*;
+        let stmt = pchar::<I>('x')
+            .seq(pchar('='))
+            .seq(pin(['1', '2']).atleast_once())
+            .seq(pchar(';'))
+            .seq(pchar('\n').maybe());
+        let prog = stmt.multiple().seq(pany().avoid()).msg("Bad statement!");
+        let ctx = core::Context::new();
+        let text = "x=1;\nx=12+2;\n";
+        match prog.run(StrState::with_context(text, &ctx)) {
+            Err((e, _)) => {
+                let rendered = e.diagnostic().render(text);
+                let expected = "\
+error: Bad statement!
+ --> 2:1
+  |
+1 | x=1;
+2 | x=12+2;
+  | ^
+  |     - expected one of `1`, `2`, `;`
+";
+                assert!(rendered == expected, "rendered:\n{}", rendered);
+            }
+            Ok((r, _)) => panic!("r was: {:?}", r),
+        }
+    }
+    #[test]
+    fn owned_error_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Pair,
+        }
+        impl core::Identifier for I {}
+        use primitives::*;
+        fn parse(text: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            let p = pchar::<I>('\n')
+                .seq(pchar('('))
+                .seq(pin(['a', 'b']).name("letter"))
+                .seq(pchar(')'))
+                .label(I::Pair)
+                .msg("Bad pair!");
+            let ctx = core::Context::new();
+            p.run(StrState::with_context(text, &ctx))
+                .map_err(|(e, _)| core::OwnedParseError::from(e))?;
+            Ok(())
+        }
+        assert!(parse("\n(a)").is_ok());
+        // the error outlives the grammar, the context and the input
+        let text = "\n(ac)".to_owned();
+        let e = std::thread::spawn(move || parse(&text).unwrap_err())
+            .join()
+            .unwrap();
+        let e = e.downcast::<core::OwnedParseError<I>>().unwrap();
+        assert!((e.offset, e.line, e.column) == (3, 1, 2), "e was: {:?}", e);
+        assert!(e.line_text == "(ac)" && e.msg == Some("Bad pair!"));
+        assert!(e.expected == "expected `)` at 1:2", "e was: {:?}", e);
+        assert!(
+            e.backtrace
+                == core::ErrorBacktrace::Node {
+                    identifier: I::Pair,
+                    next: Box::new(core::ErrorBacktrace::Empty),
+                }
+        );
+        assert!(e
+            .to_string()
+            .ends_with("(ac)\n  ^~\nNote:\nBad pair!\nexpected `)` at 1:2"));
+    }
+    #[test]
+    fn recover_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Stmt,
+        }
+        impl core::Identifier for I {}
+        use crate::core::NonTerminal::*;
+        use primitives::*;
+        let stmt = || {
+            pin(['a', 'b', 'c', 'd'])
+                .seq(pchar('='))
+                .seq(pin(['1', '2', '3']).atleast_once().catenate())
+                .seq(pchar(';'))
+                .label(I::Stmt)
+        };
+        let prog = stmt().recover(pchar(';')).multiple();
+        let s = StrState::new("a=1;b=;c=3;d=x");
+        match prog.run(s) {
+            Ok((r, s)) => {
+                assert!(s.is_empty());
+                let skipped: Vec<&str> = match &r {
+                    Congregate(v, _) => v
+                        .iter()
+                        .filter_map(|n| match n {
+                            Error { skipped, .. } => Some(*skipped),
+                            _ => None,
+                        })
+                        .collect(),
+                    _ => panic!("r was: {:?}", r),
+                };
+                assert!(skipped == ["b=;", "d=x"], "skipped was: {:?}", skipped);
+                let errors = r.errors();
+                assert!(errors.len() == 2);
+                assert!(errors[0].location.2 == 6, "errors were: {:?}", errors);
+                assert!(errors[1].location.2 == 13, "errors were: {:?}", errors);
+            }
+            Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
+        }
+        // nothing left to skip
+        assert!(stmt().recover(pchar(';')).run(StrState::new("")).is_err());
+        let ctx = core::Context::new();
+        let prog = rule::Rule::new();
+        prog.define(stmt().recover(pchar(';')).multiple());
+        let twice = prog.parser().ensure().seq(prog.parser());
+        match twice.run(StrState::with_context("b=;c=1;", &ctx)) {
+            Ok((r, _)) => assert!(r.errors().len() == 1, "r was: {:?}", r),
+            Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
+        }
+        assert!(ctx.memo_stats().hits > 0);
+    }
+    #[test]
+    fn expected_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        struct I;
+        impl core::Identifier for I {}
+        use primitives::*;
+        let num = || {
+            pin::<_, I>(['1', '2', '3', '4', '5', '6', '7', '8', '9', '0'])
+                .name("digit")
+                .atleast_once()
+        };
+        let stmt = pin(['x', 'y'])
+            .seq(pchar('='))
+            .seq(num())
+            .seq(pin(['+', '-']).seq(num()).multiple())
+            .seq(pchar(';'))
+            .seq(pchar('\n').maybe());
+        let prog = stmt.multiple().seq(pany().avoid());
+        let ctx = core::Context::new();
+        match prog.run(StrState::with_context("x=1+2;\ny=34*5;", &ctx)) {
+            Err((e, _)) => {
+                let expected = core::Expected {
+                    line: 1,
+                    column: 4,
+                    items: vec![
+                        "digit".to_owned(),
+                        "`+`".to_owned(),
+                        "`-`".to_owned(),
+                        "`;`".to_owned(),
+                    ],
+                };
+                assert!(e.expected() == Some(expected), "e was: {}", e);
+                assert!(
+                    e.to_string()
+                        .ends_with("expected one of digit, `+`, `-`, `;` at 1:4"),
+                    "e was: {}",
+                    e
+                );
+            }
+            Ok((r, _)) => panic!("r was: {:?}", r),
+        }
+        // the alternative that got furthest is reported, even without context
+        let p = pstr::<I>("ab").seq(pchar('c')).or(pchar('x'));
+        match p.run(StrState::new("abd")) {
+            Err((e, _)) => {
+                assert!(e.location.2 == 2, "e was: {}", e);
+                assert!(e.expected().is_none());
+            }
+            Ok((r, _)) => panic!("r was: {:?}", r),
+        }
+        // named parsers and memoized results report the same
+        let ctx = core::Context::packrat();
+        let p = pstr::<I>("let").name("keyword").or(pchar('x'));
+        assert!(p.run(StrState::with_context("y", &ctx)).is_err());
+        let expected = ctx.expected().unwrap();
+        assert!(expected.items == ["keyword", "`x`"], "{:?}", expected);
+        assert!(p.run(StrState::with_context("y", &ctx)).is_err());
+        assert!(ctx.expected() == Some(expected));
+        assert!(ctx.memo_stats().hits == 2);
+    }
+    #[test]
+    fn cut_passes() {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        enum I {
+            Func,
+            Call,
+        }
+        impl core::Identifier for I {}
+        use primitives::*;
+        let name = || pin(['a', 'b', 'c', 'f', 'n']).atleast_once();
+        let item = || {
+            pstr("fn")
+                .cut()
+                .seq(pchar(' '))
+                .seq(name())
+                .seq(pstr("()"))
+                .label(I::Func)
+                .or(name().seq(pstr("()")).label(I::Call))
+        };
+        let prog = item().multiple();
+        for ctx in [None, Some(core::Context::packrat())] {
+            let state = |s| match &ctx {
+                Some(ctx) => StrState::with_context(s, ctx),
+                None => StrState::new(s),
+            };
+            // `fn()` would be a call, but the cut commits to a function
+            match prog.run(state("ab()fn()")) {
+                Err((e, _)) => assert!(e.location.2 == 6, "e was: {}", e),
+                Ok((r, _)) => panic!("r was: {:?}", r),
+            }
+            match prog.run(state("fn a()ab()fn b()")) {
+                Ok((_, s)) => assert!(s.is_empty()),
+                Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
+            }
+        }
+        // committed failures are still recovered from
+        let prog = item().recover(pstr("()")).multiple();
+        match prog.run(StrState::new("fn()ab()")) {
+            Ok((r, s)) => assert!(s.is_empty() && r.errors().len() == 1),
+            Err((e, _)) => panic!("Parser Failed\n[Error]:\n{:?}", e),
+        }
+        // results before a cut are released once nothing can backtrack there
+        let ctx = core::Context::packrat();
+        let text = "fn a()".repeat(20);
+        assert!(prog.run(StrState::with_context(&text, &ctx)).is_ok());
+        assert!(ctx.memo_stats().released > 0);
+    }
 }
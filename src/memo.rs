@@ -0,0 +1,624 @@
+// Packrat memoization.
+//
+// Results are cached per parser and head, along with the trivia skipped and
+// the block indented around it, which change what it matches, in a table
+// that lives in the `Context` of a run. `NonTerminal` and `ParseError`
+// borrow the input, so the table keeps them in an owned form, with every
+// slice of the input stored as a range of positions, and rebuilds them
+// against the input on a hit.
+//
+// Every entry also records how far its parser looked into the input, so that
+// after an edit the entries that never saw the edited text can be kept, and
+// those past it shifted, to be reused by the next run.
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::core::{
+    ErrorBacktrace, Expected, Furthest, Identifier, Input, NonTerminal, ParseError, ParseResult,
+    Parser, Skip, Span, State,
+};
+use crate::indent::Level;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoStats {
+    pub hits: usize,
+    pub misses: usize,
+    /// Results dropped after cuts.
+    pub released: usize,
+}
+impl MemoStats {
+    /// Share of lookups answered from the table, between 0 and 1.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            n => self.hits as f64 / n as f64,
+        }
+    }
+}
+
+pub(crate) struct Memo {
+    everything: bool,
+    table: RefCell<Option<Box<dyn Edit>>>,
+    stats: Cell<MemoStats>,
+    // furthest position looked at by the parsers currently running
+    reach: Cell<usize>,
+}
+impl Memo {
+    pub(crate) fn new(everything: bool) -> Self {
+        Memo {
+            everything,
+            table: RefCell::new(None),
+            stats: Cell::new(MemoStats::default()),
+            reach: Cell::new(0),
+        }
+    }
+    pub(crate) fn stats(&self) -> MemoStats {
+        self.stats.get()
+    }
+    fn with_table<T: Identifier, S: ?Sized + Input, R>(
+        &self,
+        f: impl FnOnce(&mut Table<T, S>) -> R,
+    ) -> R {
+        let mut table = self.table.borrow_mut();
+        let table = table.get_or_insert_with(|| Box::new(Table::<T, S>::new()));
+        f(table
+            .as_any()
+            .downcast_mut()
+            .expect("Context was used with grammars of different Identifier or Input types!"))
+    }
+    /// Drops every stored result, for a run over other input.
+    pub(crate) fn clear(&self) {
+        self.table.borrow_mut().take();
+    }
+    /// The furthest position looked at so far, see `reached`.
+    pub(crate) fn reach(&self) -> usize {
+        self.reach.get()
+    }
+    /// Records that a parser looked at the input up to, but excluding, `to`.
+    pub(crate) fn reached(&self, to: usize) {
+        self.reach.set(self.reach.get().max(to));
+    }
+    /// Updates the table after the input was edited, see `Shift`.
+    pub(crate) fn edit(&self, shift: &Shift) {
+        if let Some(table) = self.table.borrow_mut().as_mut() {
+            table.edit(shift);
+        }
+    }
+    /// Drops the results stored for positions before `below`.
+    pub(crate) fn release(&self, below: usize) {
+        if let Some(table) = self.table.borrow_mut().as_mut() {
+            let mut stats = self.stats.get();
+            stats.released += table.release(below);
+            self.stats.set(stats);
+        }
+    }
+    fn count(&self, hit: bool) {
+        let mut stats = self.stats.get();
+        if hit {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+        self.stats.set(stats);
+    }
+    /// Runs `p` through the table: a hit rebuilds the stored result, a miss
+    /// runs the parser and stores what it returned.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn cached<'a, T: Identifier, S: ?Sized + Input>(
+        &self,
+        p: &'a dyn Parser<T, S>,
+        input: State<'a, S>,
+    ) -> ParseResult<'a, T, S> {
+        // parsers without fields all sit at the same dangling address, so it
+        // cannot tell them apart, and they are cheap enough to just run
+        if std::mem::size_of_val(p) == 0 {
+            return p.run(input);
+        }
+        let key = Key::new(p as *const dyn Parser<T, S> as *const () as usize, input);
+        if let Some(res) =
+            self.with_table(|t: &mut Table<T, S>| t.entries.get(&key).map(|m| m.replay(p, input)))
+        {
+            self.count(true);
+            return res;
+        }
+        self.count(false);
+        let outer = Seen::enter(input);
+        let res = p.run(input.uncut());
+        let entry = Memoized {
+            entry: Entry::store(&res),
+            seen: Seen::leave(input, outer),
+        };
+        self.with_table(|t: &mut Table<T, S>| t.entries.insert(key, entry));
+        within(res, input)
+    }
+    /// Runs the body `p` of the rule identified by `rule` through the table,
+    /// supporting left recursion by growing a seed:
+    ///
+    /// While the rule is running, reentering it at the same head fails, which
+    /// lets the body match without the recursive alternative. If that
+    /// happened, the result is stored and the body is rerun, now finding the
+    /// stored result when it recurses, for as long as this consumes more
+    /// input. Results at the same head depend on the stored one, so they are
+    /// dropped before every round.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn grow<'a, T: Identifier, S: ?Sized + Input>(
+        &self,
+        rule: usize,
+        p: &'a dyn Parser<T, S>,
+        input: State<'a, S>,
+    ) -> ParseResult<'a, T, S> {
+        let key = Key::new(rule, input);
+        let found = self.with_table(|t: &mut Table<T, S>| {
+            if let Some(recursed) = t.pending.get_mut(&key) {
+                *recursed = true;
+                return Some(Err((
+                    ParseError {
+                        location: (input.line_of(), input.line, input.column),
+                        head: input.head,
+                        expected: p,
+                        backtrace: ErrorBacktrace::Empty,
+                        msg: Some("Left recursion"),
+                        ctx: input.ctx,
+                    },
+                    input,
+                )));
+            }
+            t.entries.get(&key).map(|m| m.replay(p, input))
+        });
+        if let Some(res) = found {
+            self.count(true);
+            return res;
+        }
+        self.count(false);
+        self.with_table(|t: &mut Table<T, S>| t.pending.insert(key, false));
+        let outer = Seen::enter(input);
+        let mut res = input.backtrack(false, || p.run(input.uncut()));
+        let recursed = self.with_table(|t: &mut Table<T, S>| t.pending.remove(&key));
+        if recursed == Some(true) {
+            while let Ok((_, s)) = &res {
+                let end = s.head;
+                let entry = Memoized {
+                    entry: Entry::store(&res),
+                    seen: Seen::peek(input),
+                };
+                self.with_table(|t: &mut Table<T, S>| {
+                    t.entries.retain(|k, _| k.head != input.head);
+                    t.entries.insert(key, entry);
+                });
+                let next = input.backtrack(false, || p.run(input.uncut()));
+                if next.as_ref().is_ok_and(|(_, s)| s.head > end) {
+                    res = next;
+                } else {
+                    break;
+                }
+            }
+        }
+        let entry = Memoized {
+            entry: Entry::store(&res),
+            seen: Seen::leave(input, outer),
+        };
+        self.with_table(|t: &mut Table<T, S>| t.entries.insert(key, entry));
+        within(res, input)
+    }
+}
+
+// Results are stored as if no cut was passed before them, and the states
+// returned adjusted to the cut of the input they were run at.
+#[allow(clippy::result_large_err)]
+fn within<'a, T: Identifier, S: ?Sized + Input>(
+    res: ParseResult<'a, T, S>,
+    input: State<'a, S>,
+) -> ParseResult<'a, T, S> {
+    match res {
+        Ok((n, s)) => Ok((
+            n,
+            State {
+                cut: s.cut || input.cut,
+                ..s
+            },
+        )),
+        Err((e, s)) => Err((
+            e,
+            State {
+                cut: s.cut || input.cut,
+                ..s
+            },
+        )),
+    }
+}
+
+/// Runs a child parser, going through the packrat table when the input
+/// carries a context that memoizes the whole grammar.
+#[allow(clippy::result_large_err)]
+pub(crate) fn apply<'a, T: Identifier, S: ?Sized + Input>(
+    p: &'a dyn Parser<T, S>,
+    input: State<'a, S>,
+) -> ParseResult<'a, T, S> {
+    match input.ctx {
+        Some(ctx) if ctx.memo.everything => ctx.memo.cached(p, input),
+        _ => p.run(input),
+    }
+}
+
+/// How an edit moved the input: the text from `start` up to `end` was
+/// replaced, so that what followed now starts at `new_end`. `end_at` and
+/// `new_end_at` are the lines and columns of `end` and `new_end`.
+pub(crate) struct Shift {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) new_end: usize,
+    pub(crate) end_at: (usize, usize),
+    pub(crate) new_end_at: (usize, usize),
+}
+impl Shift {
+    fn head(&self, head: usize) -> usize {
+        head - self.end + self.new_end
+    }
+    fn line_column(&self, line: usize, column: usize) -> (usize, usize) {
+        let column = match line == self.end_at.0 {
+            true => column - self.end_at.1 + self.new_end_at.1,
+            false => column,
+        };
+        (line - self.end_at.0 + self.new_end_at.0, column)
+    }
+    fn span(&self, span: Span) -> Span {
+        let (line, column) = self.line_column(span.line, span.column);
+        let (end_line, end_column) = self.line_column(span.end_line, span.end_column);
+        Span {
+            start: self.head(span.start),
+            end: self.head(span.end),
+            line,
+            column,
+            end_line,
+            end_column,
+        }
+    }
+}
+
+// tables are stored without their types, which editing does not need
+trait Edit {
+    fn as_any(&mut self) -> &mut dyn Any;
+    fn edit(&mut self, shift: &Shift);
+    fn release(&mut self, below: usize) -> usize;
+}
+impl<T: Identifier, S: ?Sized + Input> Edit for Table<T, S> {
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+    // entries that looked no further than the edit stay, those starting
+    // after it move along, and the others are dropped. One starting right
+    // after it is dropped too if the edit changed whether that is the start
+    // of a line, which parsers of indentation check without looking.
+    fn edit(&mut self, shift: &Shift) {
+        let still_starts = (shift.end_at.1 == 0) == (shift.new_end_at.1 == 0);
+        self.entries = std::mem::take(&mut self.entries)
+            .into_iter()
+            .filter_map(|(key, m)| {
+                if m.seen.reach <= shift.start {
+                    Some((key, m))
+                } else if key.head > shift.end || key.head == shift.end && still_starts {
+                    let head = shift.head(key.head);
+                    Some((Key { head, ..key }, m.shift(shift)))
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+    fn release(&mut self, below: usize) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|k, _| k.head >= below);
+        before - self.entries.len()
+    }
+}
+
+// What a parser looked at and expected while it ran, which a hit replays.
+struct Seen {
+    // the furthest position it looked at
+    reach: usize,
+    furthest: Option<Furthest>,
+}
+impl Seen {
+    // starts recording for a parser at `input`, returning what the parsers
+    // around it saw so far
+    fn enter<S: ?Sized + Input>(input: State<'_, S>) -> Seen {
+        let ctx = input.ctx.unwrap();
+        Seen {
+            reach: ctx.memo.reach.replace(input.head),
+            furthest: ctx.take_furthest(),
+        }
+    }
+    // returns what the parser saw, adding it to what the parsers around it
+    // saw before
+    fn leave<S: ?Sized + Input>(input: State<'_, S>, outer: Seen) -> Seen {
+        let seen = Seen::peek(input);
+        let ctx = input.ctx.unwrap();
+        ctx.take_furthest();
+        outer.replay(input);
+        seen.replay(input);
+        seen
+    }
+    fn peek<S: ?Sized + Input>(input: State<'_, S>) -> Seen {
+        let ctx = input.ctx.unwrap();
+        let furthest = ctx.take_furthest();
+        ctx.merge_furthest(furthest.clone());
+        Seen {
+            reach: ctx.memo.reach.get(),
+            furthest,
+        }
+    }
+    fn replay<S: ?Sized + Input>(&self, input: State<'_, S>) {
+        let ctx = input.ctx.unwrap();
+        ctx.memo.reached(self.reach);
+        ctx.merge_furthest(self.furthest.clone());
+    }
+    fn shift(self, shift: &Shift) -> Self {
+        Seen {
+            reach: shift.head(self.reach),
+            furthest: self.furthest.map(|f| {
+                let (line, column) = shift.line_column(f.expected.line, f.expected.column);
+                Furthest {
+                    head: shift.head(f.head),
+                    expected: Expected {
+                        line,
+                        column,
+                        ..f.expected
+                    },
+                }
+            }),
+        }
+    }
+}
+
+struct Memoized<T: Identifier> {
+    entry: Entry<T>,
+    seen: Seen,
+}
+impl<T: Identifier> Memoized<T> {
+    #[allow(clippy::result_large_err)]
+    fn replay<'a, S: ?Sized + Input>(
+        &self,
+        p: &'a dyn Parser<T, S>,
+        input: State<'a, S>,
+    ) -> ParseResult<'a, T, S> {
+        self.seen.replay(input);
+        self.entry.rebuild(p, input)
+    }
+    fn shift(self, shift: &Shift) -> Self {
+        Memoized {
+            entry: self.entry.shift(shift),
+            seen: self.seen.shift(shift),
+        }
+    }
+}
+
+// The parser or rule a result is stored for, where it ran, and what it ran
+// within: the trivia skipped, by address, and the level of the block.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    parser: usize,
+    head: usize,
+    trivia: usize,
+    indent: Level,
+}
+impl Key {
+    fn new<S: ?Sized + Input>(parser: usize, input: State<'_, S>) -> Self {
+        Key {
+            parser,
+            head: input.head,
+            trivia: input
+                .trivia
+                .map_or(0, |t| t as *const dyn Skip<S> as *const () as usize),
+            indent: input.indent,
+        }
+    }
+}
+
+struct Table<T: Identifier, S: ?Sized + Input> {
+    entries: HashMap<Key, Memoized<T>>,
+    // rules currently running, and whether they were reentered
+    pending: HashMap<Key, bool>,
+    input: PhantomData<S>,
+}
+impl<T: Identifier, S: ?Sized + Input> Table<T, S> {
+    fn new() -> Self {
+        Table {
+            entries: HashMap::new(),
+            pending: HashMap::new(),
+            input: PhantomData,
+        }
+    }
+}
+
+enum Tree<T: Identifier> {
+    Node {
+        identifier: T,
+        children: Box<Self>,
+        span: Span,
+    },
+    Congregate(Vec<Self>, Span),
+    Leaf(Span),
+    Empty(Span),
+    Error(Failure<T>, Span),
+}
+impl<T: Identifier> Tree<T> {
+    fn store<S: ?Sized + Input>(n: &NonTerminal<'_, T, S>) -> Self {
+        match n {
+            NonTerminal::Node {
+                identifier,
+                children,
+                span,
+            } => Tree::Node {
+                identifier: *identifier,
+                children: Box::new(Tree::store(children)),
+                span: *span,
+            },
+            NonTerminal::Congregate(v, span) => {
+                Tree::Congregate(v.iter().map(Tree::store).collect(), *span)
+            }
+            NonTerminal::Leaf(_, span) => Tree::Leaf(*span),
+            NonTerminal::Empty(span) => Tree::Empty(*span),
+            NonTerminal::Error { error, span, .. } => Tree::Error(Failure::store(error), *span),
+        }
+    }
+    fn rebuild<'a, S: ?Sized + Input>(
+        &self,
+        p: &'a dyn Parser<T, S>,
+        input: State<'a, S>,
+    ) -> NonTerminal<'a, T, S> {
+        match self {
+            Tree::Node {
+                identifier,
+                children,
+                span,
+            } => NonTerminal::Node {
+                identifier: *identifier,
+                children: Box::new(children.rebuild(p, input)),
+                span: *span,
+            },
+            Tree::Congregate(v, span) => {
+                NonTerminal::Congregate(v.iter().map(|n| n.rebuild(p, input)).collect(), *span)
+            }
+            Tree::Leaf(span) => NonTerminal::Leaf(span.slice(input.string), *span),
+            Tree::Empty(span) => NonTerminal::Empty(*span),
+            Tree::Error(f, span) => NonTerminal::Error {
+                error: Box::new(f.rebuild(p, input)),
+                skipped: span.slice(input.string),
+                span: *span,
+            },
+        }
+    }
+    fn shift(self, shift: &Shift) -> Self {
+        match self {
+            Tree::Node {
+                identifier,
+                children,
+                span,
+            } => Tree::Node {
+                identifier,
+                children: Box::new(children.shift(shift)),
+                span: shift.span(span),
+            },
+            Tree::Congregate(v, span) => Tree::Congregate(
+                v.into_iter().map(|n| n.shift(shift)).collect(),
+                shift.span(span),
+            ),
+            Tree::Leaf(span) => Tree::Leaf(shift.span(span)),
+            Tree::Empty(span) => Tree::Empty(shift.span(span)),
+            Tree::Error(f, span) => Tree::Error(f.shift(shift), shift.span(span)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Position {
+    head: usize,
+    column: usize,
+    line: usize,
+    cut: bool,
+}
+impl Position {
+    fn store<S: ?Sized + Input>(s: State<'_, S>) -> Self {
+        Position {
+            head: s.head,
+            column: s.column,
+            line: s.line,
+            cut: s.cut,
+        }
+    }
+    fn shift(self, shift: &Shift) -> Self {
+        let (line, column) = shift.line_column(self.line, self.column);
+        Position {
+            head: shift.head(self.head),
+            column,
+            line,
+            ..self
+        }
+    }
+    fn rebuild<S: ?Sized + Input>(self, input: State<'_, S>) -> State<'_, S> {
+        State {
+            head: self.head,
+            column: self.column,
+            line: self.line,
+            cut: self.cut || input.cut,
+            ..input
+        }
+    }
+}
+
+/// A stored error. The parser that actually failed is not kept, so a
+/// rebuilt error names the memoized parser as the one that was expected.
+struct Failure<T: Identifier> {
+    head: usize,
+    line: usize,
+    column: usize,
+    backtrace: ErrorBacktrace<T>,
+    msg: Option<&'static str>,
+}
+impl<T: Identifier> Failure<T> {
+    fn store<S: ?Sized + Input>(e: &ParseError<'_, T, S>) -> Self {
+        Failure {
+            head: e.head,
+            line: e.location.1,
+            column: e.location.2,
+            backtrace: e.backtrace.clone(),
+            msg: e.msg,
+        }
+    }
+    fn shift(self, shift: &Shift) -> Self {
+        let (line, column) = shift.line_column(self.line, self.column);
+        Failure {
+            head: shift.head(self.head),
+            line,
+            column,
+            ..self
+        }
+    }
+    fn rebuild<'a, S: ?Sized + Input>(
+        &self,
+        p: &'a dyn Parser<T, S>,
+        input: State<'a, S>,
+    ) -> ParseError<'a, T, S> {
+        ParseError {
+            location: (input.string.line_of(self.line), self.line, self.column),
+            head: self.head,
+            expected: p,
+            backtrace: self.backtrace.clone(),
+            msg: self.msg,
+            ctx: input.ctx,
+        }
+    }
+}
+
+enum Entry<T: Identifier> {
+    Success(Tree<T>, Position),
+    Failure(Failure<T>, Position),
+}
+impl<T: Identifier> Entry<T> {
+    fn store<S: ?Sized + Input>(res: &ParseResult<'_, T, S>) -> Self {
+        match res {
+            Ok((n, s)) => Entry::Success(Tree::store(n), Position::store(*s)),
+            Err((e, s)) => Entry::Failure(Failure::store(e), Position::store(*s)),
+        }
+    }
+    fn shift(self, shift: &Shift) -> Self {
+        match self {
+            Entry::Success(n, s) => Entry::Success(n.shift(shift), s.shift(shift)),
+            Entry::Failure(f, s) => Entry::Failure(f.shift(shift), s.shift(shift)),
+        }
+    }
+    #[allow(clippy::result_large_err)]
+    fn rebuild<'a, S: ?Sized + Input>(
+        &self,
+        p: &'a dyn Parser<T, S>,
+        input: State<'a, S>,
+    ) -> ParseResult<'a, T, S> {
+        match self {
+            Entry::Success(n, s) => Ok((n.rebuild(p, input), s.rebuild(input))),
+            Entry::Failure(f, s) => Err((f.rebuild(p, input), s.rebuild(input))),
+        }
+    }
+}
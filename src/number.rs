@@ -0,0 +1,208 @@
+// Numeric literals.
+//
+// `Number` describes the syntax of the literals a grammar accepts, which
+// `pnumber` parses and `integer` and `float` turn into values:
+//
+// sign     <- ('+' / '-')?                    with sign()
+// number   <- sign (radix / decimal)
+// radix    <- '0x' hex / '0o' octal / '0b' binary     with prefixes()
+// decimal  <- digits ('.' digits)? exponent?  fraction with fraction()
+// exponent <- [eE] ('+' / '-')? digits        with exponent()
+// digits   <- digit ('_'? digit)*             separators with underscores()
+//
+// A fraction needs digits after its dot, so `1..2` and `1.max(2)` start with
+// the integer `1`.
+
+use crate::core::Identifier;
+use crate::primitives::pnumber;
+use crate::typed::{leaf, Semantic, Typed};
+
+// where a literal is malformed, why, and what was expected there
+type Malformed = (usize, &'static str, &'static str);
+
+/// The syntax of numeric literals. All parts but the decimal digits are
+/// off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Number {
+    sign: bool,
+    prefixes: bool,
+    underscores: bool,
+    fraction: bool,
+    exponent: bool,
+}
+impl Number {
+    /// Decimal integers such as `42`.
+    pub fn integer() -> Self {
+        Self::default()
+    }
+    /// Decimal numbers such as `42`, `4.2` and `4.2e-1`.
+    pub fn float() -> Self {
+        Self::default().fraction(true).exponent(true)
+    }
+    /// A leading `+` or `-`.
+    pub fn sign(mut self, on: bool) -> Self {
+        self.sign = on;
+        self
+    }
+    /// Hexadecimal, octal and binary integers, prefixed with `0x`, `0o` and
+    /// `0b`.
+    pub fn prefixes(mut self, on: bool) -> Self {
+        self.prefixes = on;
+        self
+    }
+    /// Underscores between digits, such as in `1_000_000`.
+    pub fn underscores(mut self, on: bool) -> Self {
+        self.underscores = on;
+        self
+    }
+    pub fn fraction(mut self, on: bool) -> Self {
+        self.fraction = on;
+        self
+    }
+    pub fn exponent(mut self, on: bool) -> Self {
+        self.exponent = on;
+        self
+    }
+
+    /// The length of the literal `s` starts with, or where in `s` and why it
+    /// is malformed.
+    pub(crate) fn scan(&self, s: &str) -> Result<usize, Malformed> {
+        let b = s.as_bytes();
+        let mut at = 0;
+        if self.sign && matches!(b.first(), Some(b'+' | b'-')) {
+            at = 1;
+            if !b.get(at).is_some_and(u8::is_ascii_digit) {
+                return Err((at, "Digit expected after sign!", "digit"));
+            }
+        }
+        if self.prefixes && b.get(at) == Some(&b'0') {
+            let radix = match b.get(at + 1) {
+                Some(b'x') => Some((16, "Hexadecimal digit expected after `0x`!")),
+                Some(b'o') => Some((8, "Octal digit expected after `0o`!")),
+                Some(b'b') => Some((2, "Binary digit expected after `0b`!")),
+                _ => None,
+            };
+            if let Some((radix, missing)) = radix {
+                at = self.digits(s, at + 2, radix, missing)?;
+                if radix < 10 && b.get(at).is_some_and(u8::is_ascii_digit) {
+                    return Err((at, "Digit out of range for the radix!", digit(radix)));
+                }
+                return Ok(at);
+            }
+        }
+        at = self.digits(s, at, 10, "Digit expected!")?;
+        if self.fraction
+            && b.get(at) == Some(&b'.')
+            && b.get(at + 1).is_some_and(u8::is_ascii_digit)
+        {
+            at = self.digits(s, at + 1, 10, "Digit expected after `.`!")?;
+        }
+        if self.exponent && matches!(b.get(at), Some(b'e' | b'E')) {
+            at += 1;
+            if matches!(b.get(at), Some(b'+' | b'-')) {
+                at += 1;
+            }
+            at = self.digits(s, at, 10, "Digit expected after exponent!")?;
+        }
+        Ok(at)
+    }
+    // the end of the digits from `at`, which must start with one
+    fn digits(
+        &self,
+        s: &str,
+        mut at: usize,
+        radix: u32,
+        missing: &'static str,
+    ) -> Result<usize, Malformed> {
+        let is_digit = |at: usize| s[at..].chars().next().is_some_and(|c| c.is_digit(radix));
+        if !is_digit(at) {
+            return Err((at, missing, digit(radix)));
+        }
+        loop {
+            if is_digit(at) {
+                at += 1;
+            } else if self.underscores && s[at..].starts_with('_') {
+                if !is_digit(at + 1) {
+                    return Err((at + 1, "Digit expected after `_`!", digit(radix)));
+                }
+                at += 1;
+            } else {
+                return Ok(at);
+            }
+        }
+    }
+    /// The value of `text`, an integer literal of this syntax, unless it is
+    /// too large or has a fraction or an exponent.
+    pub fn int_value(&self, text: &str) -> Option<i128> {
+        let digits: String = text.chars().filter(|c| *c != '_').collect();
+        let (negative, digits) = match digits.as_bytes().first() {
+            Some(b'-') => (true, &digits[1..]),
+            Some(b'+') => (false, &digits[1..]),
+            _ => (false, &digits[..]),
+        };
+        let (radix, digits) = match digits.get(..2) {
+            Some("0x") => (16, &digits[2..]),
+            Some("0o") => (8, &digits[2..]),
+            Some("0b") => (2, &digits[2..]),
+            _ => (10, digits),
+        };
+        // parsed with its sign, so that the smallest value fits
+        let signed = format!("{}{}", if negative { "-" } else { "" }, digits);
+        i128::from_str_radix(&signed, radix).ok()
+    }
+    /// The value of `text`, a literal of this syntax.
+    pub fn float_value(&self, text: &str) -> f64 {
+        let digits: String = text.chars().filter(|c| *c != '_').collect();
+        let (negative, unsigned) = match digits.as_bytes().first() {
+            Some(b'-') => (true, &digits[1..]),
+            Some(b'+') => (false, &digits[1..]),
+            _ => (false, &digits[..]),
+        };
+        let radix = match unsigned.get(..2) {
+            Some("0x") => 16,
+            Some("0o") => 8,
+            Some("0b") => 2,
+            _ => return digits.parse().unwrap_or(f64::NAN),
+        };
+        if let Some(i) = self.int_value(text) {
+            return i as f64;
+        }
+        // too large for an integer, so summed up as a float
+        let value = unsigned[2..].chars().fold(0.0, |v, c| {
+            v * radix as f64 + c.to_digit(radix).unwrap_or(0) as f64
+        });
+        if negative {
+            -value
+        } else {
+            value
+        }
+    }
+}
+
+// what a digit of `radix` is called
+fn digit(radix: u32) -> &'static str {
+    match radix {
+        16 => "hexadecimal digit",
+        8 => "octal digit",
+        2 => "binary digit",
+        _ => "digit",
+    }
+}
+
+/// Parses an integer literal of `syntax` into its value, failing if it does
+/// not fit, or if it has a fraction or an exponent that `syntax` allows.
+pub fn integer<T: Identifier>(syntax: Number) -> Typed<T, i128> {
+    pnumber(syntax).and_then(move |n| {
+        let text = leaf(&n);
+        let unsigned = text.trim_start_matches(['+', '-']);
+        let prefixed = matches!(unsigned.get(..2), Some("0x" | "0o" | "0b"));
+        if !prefixed && unsigned.contains(['.', 'e', 'E']) {
+            return Err("Integer expected, not a fraction or exponent!");
+        }
+        syntax.int_value(text).ok_or("Integer too large!")
+    })
+}
+/// Parses a literal of `syntax` into its value as a float.
+pub fn float<T: Identifier>(syntax: Number) -> Typed<T, f64> {
+    pnumber(syntax).map(move |n| syntax.float_value(leaf(&n)))
+}
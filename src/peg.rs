@@ -0,0 +1,387 @@
+// Textual PEG grammars, compiled at runtime into a tree of parsers.
+//
+// grammar  <- _ rule+ !.
+// rule     <- name _ '<-' _ choice (';' _)?
+// choice   <- sequence ('/' _ sequence)*
+// sequence <- prefix+
+// prefix   <- ('&' / '!')? _ suffix
+// suffix   <- primary ('*' / '+' / '?')? _
+// primary  <- name _ !'<-' / '(' _ choice ')' / literal / class / '.'
+// literal  <- '"' ('\' . / [^"])* '"' / "'" ('\' . / [^'])* "'"
+// class    <- '[' '^'? (char '-' char / char)* ']'
+// _        <- ([ \t\r\n] / '#' [^\n]*)*
+//
+// The grammar of grammars is itself written with page.
+
+use std::collections::HashMap;
+
+use crate::core::{Context, ErrorBacktrace, Identifier, NonTerminal, ParseError, Parser, StrState};
+use crate::primitives::{pany, pchar, pexcept, pin, ppredicate, pstr};
+use crate::rule::Rule;
+
+/// Labels of the nodes produced when parsing a grammar's text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Rule,
+    Name,
+    Choice,
+    Sequence,
+    Prefix,
+    Suffix,
+    And,
+    Not,
+    Star,
+    Plus,
+    Question,
+    Group,
+    Literal,
+    Text,
+    Class,
+    Negated,
+    Range,
+    Char,
+    Dot,
+}
+impl Identifier for Syntax {}
+
+fn name_length(s: &str) -> (bool, usize) {
+    let mut l = 0;
+    for c in s.chars() {
+        if c == '_' || c.is_ascii_alphabetic() || (l > 0 && c.is_ascii_digit()) {
+            l += c.len_utf8();
+        } else {
+            break;
+        }
+    }
+    (l > 0, l)
+}
+
+/// Compiles textual PEG grammars into parsers.
+pub struct Peg {
+    grammar: Box<dyn Parser<Syntax>>,
+    name: Box<dyn Parser<Syntax>>,
+    // the context of the last compilation, which syntax errors refer to
+    ctx: Context,
+}
+impl Peg {
+    pub fn new() -> Self {
+        let spacing = || {
+            pin([' ', '\t', '\r', '\n'])
+                .or(pchar('#').seq(pexcept(['\n']).multiple()))
+                .multiple()
+                .ignore()
+        };
+        let name = || ppredicate(name_length).label(Syntax::Name);
+        let quoted = |q: char| {
+            pchar(q)
+                .seq(
+                    pchar('\\')
+                        .seq(pany())
+                        .or(pexcept([q]))
+                        .multiple()
+                        .catenate()
+                        .label(Syntax::Text),
+                )
+                .seq(pchar(q))
+        };
+        let class_char = || {
+            pchar('\\')
+                .seq(pany())
+                .or(pexcept([']']))
+                .catenate()
+                .label(Syntax::Char)
+        };
+        let choice = Rule::new();
+        let primary = name()
+            .seq(spacing())
+            .seq(pstr("<-").avoid())
+            .or(pchar('(')
+                .seq(spacing())
+                .seq(choice.parser())
+                .seq(pchar(')'))
+                .label(Syntax::Group))
+            .or(quoted('"').or(quoted('\'')).label(Syntax::Literal))
+            .or(pchar('[')
+                .seq(pchar('^').label(Syntax::Negated).maybe())
+                .seq(
+                    class_char()
+                        .seq(pchar('-'))
+                        .seq(class_char())
+                        .label(Syntax::Range)
+                        .or(class_char())
+                        .multiple(),
+                )
+                .seq(pchar(']'))
+                .label(Syntax::Class))
+            .or(pchar('.').label(Syntax::Dot));
+        let suffix = primary
+            .seq(
+                pchar('*')
+                    .label(Syntax::Star)
+                    .or(pchar('+').label(Syntax::Plus))
+                    .or(pchar('?').label(Syntax::Question))
+                    .maybe(),
+            )
+            .seq(spacing())
+            .label(Syntax::Suffix);
+        let prefix = pchar('&')
+            .label(Syntax::And)
+            .or(pchar('!').label(Syntax::Not))
+            .maybe()
+            .seq(spacing())
+            .seq(suffix)
+            .label(Syntax::Prefix);
+        let sequence = Rule::new();
+        sequence.define(prefix.atleast_once().label(Syntax::Sequence));
+        choice.define(
+            sequence
+                .parser()
+                .seq(pchar('/').seq(spacing()).seq(sequence.parser()).multiple())
+                .label(Syntax::Choice),
+        );
+        let rule = name()
+            .seq(spacing())
+            .seq(pstr("<-"))
+            .seq(spacing())
+            .seq(choice.parser())
+            .seq(pchar(';').seq(spacing()).maybe())
+            .label(Syntax::Rule);
+        Peg {
+            grammar: spacing().seq(rule.atleast_once()).seq(pany().avoid()),
+            name: name(),
+            ctx: Context::new(),
+        }
+    }
+}
+impl Default for Peg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Peg {
+    /// Compiles the rules in `text`. Every rule for which `labels` returns
+    /// an identifier is labeled with it.
+    ///
+    /// Syntax errors, references to undefined rules, rules defined twice and
+    /// reversed class ranges are reported as errors pointing into `text`.
+    /// Syntax errors point at the furthest position the grammar of grammars
+    /// got to, and tell what it expected there.
+    pub fn compile<'a, T: Identifier>(
+        &'a self,
+        text: &'a str,
+        labels: impl Fn(&str) -> Option<T>,
+    ) -> Result<Grammar<T>, ParseError<'a, Syntax>> {
+        self.ctx.memo.clear();
+        let tree = match self.grammar.run(StrState::with_context(text, &self.ctx)) {
+            Ok((n, _)) => n,
+            Err((e, _)) => {
+                let furthest = self.ctx.take_furthest();
+                self.ctx.merge_furthest(furthest.clone());
+                return Err(match furthest {
+                    Some(f) => self.error_at(text, f.head, None, Some(&self.ctx)),
+                    None => e,
+                });
+            }
+        };
+        let mut grammar = Grammar {
+            rules: HashMap::new(),
+            first: String::new(),
+        };
+        let rules = nodes(&tree);
+        for (_, r) in rules.iter() {
+            let name = leaf(nodes(r)[0].1);
+            if grammar.rules.contains_key(name) {
+                return Err(self.error(text, name, "Rule was defined twice!"));
+            }
+            if grammar.rules.is_empty() {
+                grammar.first = name.to_owned();
+            }
+            grammar.rules.insert(name.to_owned(), Rule::new());
+        }
+        for (_, r) in rules.iter() {
+            let children = nodes(r);
+            let name = leaf(children[0].1);
+            let mut body = self.choice(text, &grammar, children[1].1)?;
+            if let Some(label) = labels(name) {
+                body = body.label(label);
+            }
+            grammar.rules[name].define(body);
+        }
+        Ok(grammar)
+    }
+    fn error<'a>(&'a self, text: &'a str, at: &str, msg: &'static str) -> ParseError<'a, Syntax> {
+        let head = at.as_ptr() as usize - text.as_ptr() as usize;
+        self.error_at(text, head, Some(msg), None)
+    }
+    fn error_at<'a>(
+        &'a self,
+        text: &'a str,
+        head: usize,
+        msg: Option<&'static str>,
+        ctx: Option<&'a Context>,
+    ) -> ParseError<'a, Syntax> {
+        let state = StrState::new(text).advance(head);
+        ParseError {
+            location: (state.line_of(), state.line, state.column),
+            head: state.head,
+            expected: self.name.as_ref(),
+            backtrace: ErrorBacktrace::Empty,
+            msg,
+            ctx,
+        }
+    }
+    fn choice<'a, T: Identifier>(
+        &'a self,
+        text: &'a str,
+        grammar: &Grammar<T>,
+        n: &NonTerminal<'a, Syntax>,
+    ) -> Result<Box<dyn Parser<T>>, ParseError<'a, Syntax>> {
+        let mut alternatives = vec![];
+        for (_, sequence) in nodes(n) {
+            let mut items = vec![];
+            for (_, prefix) in nodes(sequence) {
+                items.push(self.prefix(text, grammar, prefix)?);
+            }
+            alternatives.push(items.into_iter().reduce(|a, b| a.seq(b)).unwrap());
+        }
+        Ok(alternatives.into_iter().reduce(|a, b| a.or(b)).unwrap())
+    }
+    fn prefix<'a, T: Identifier>(
+        &'a self,
+        text: &'a str,
+        grammar: &Grammar<T>,
+        n: &NonTerminal<'a, Syntax>,
+    ) -> Result<Box<dyn Parser<T>>, ParseError<'a, Syntax>> {
+        let children = nodes(n);
+        let (op, suffix) = match children[..] {
+            [(op, _), (_, suffix)] => (Some(op), suffix),
+            [(_, suffix)] => (None, suffix),
+            _ => unreachable!(),
+        };
+        let children = nodes(suffix);
+        let mut p = self.primary(text, grammar, children[0])?;
+        p = match children.get(1) {
+            Some((Syntax::Star, _)) => p.multiple(),
+            Some((Syntax::Plus, _)) => p.atleast_once(),
+            Some((Syntax::Question, _)) => p.maybe(),
+            _ => p,
+        };
+        Ok(match op {
+            Some(Syntax::And) => p.ensure(),
+            Some(Syntax::Not) => p.avoid(),
+            _ => p,
+        })
+    }
+    fn primary<'a, T: Identifier>(
+        &'a self,
+        text: &'a str,
+        grammar: &Grammar<T>,
+        (label, n): (Syntax, &NonTerminal<'a, Syntax>),
+    ) -> Result<Box<dyn Parser<T>>, ParseError<'a, Syntax>> {
+        Ok(match label {
+            Syntax::Name => match grammar.rules.get(leaf(n)) {
+                Some(r) => r.parser(),
+                None => return Err(self.error(text, leaf(n), "Undefined rule!")),
+            },
+            Syntax::Group => self.choice(text, grammar, nodes(n)[0].1)?,
+            Syntax::Literal => {
+                let content = leaf(nodes(n)[0].1);
+                let s = unescape(content);
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (None, _) => return Err(self.error(text, content, "Empty literal!")),
+                    (Some(c), None) => pchar(c),
+                    _ => pstr(s),
+                }
+            }
+            Syntax::Class => {
+                let mut negated = false;
+                let mut ranges = vec![];
+                for (label, item) in nodes(n) {
+                    match label {
+                        Syntax::Negated => negated = true,
+                        Syntax::Range => {
+                            let bounds = nodes(item);
+                            let first = unescape(leaf(bounds[0].1)).chars().next().unwrap();
+                            let last = unescape(leaf(bounds[1].1)).chars().next().unwrap();
+                            if first > last {
+                                let at = leaf(bounds[0].1);
+                                return Err(self.error(text, at, "Reversed class range!"));
+                            }
+                            ranges.push((first, last));
+                        }
+                        _ => {
+                            let c = unescape(leaf(item)).chars().next().unwrap();
+                            ranges.push((c, c));
+                        }
+                    }
+                }
+                ppredicate(move |s| {
+                    let c = s.chars().next().unwrap();
+                    let found = ranges
+                        .iter()
+                        .any(|(first, last)| (*first..=*last).contains(&c));
+                    (found != negated, c.len_utf8())
+                })
+            }
+            _ => pany(),
+        })
+    }
+}
+
+/// The rules of a compiled grammar.
+pub struct Grammar<T: Identifier> {
+    rules: HashMap<String, Rule<T>>,
+    first: String,
+}
+impl<T: Identifier> Grammar<T> {
+    /// A parser for the rule called `name`, if there is one.
+    pub fn rule(&self, name: &str) -> Option<Box<dyn Parser<T>>> {
+        self.rules.get(name).map(|r| r.parser())
+    }
+    /// A parser for the first rule of the grammar.
+    pub fn start(&self) -> Box<dyn Parser<T>> {
+        self.rules[&self.first].parser()
+    }
+}
+
+// the labeled nodes directly below `n`
+fn nodes<'t, 'a>(n: &'t NonTerminal<'a, Syntax>) -> Vec<(Syntax, &'t NonTerminal<'a, Syntax>)> {
+    match n {
+        NonTerminal::Node {
+            identifier,
+            children,
+            ..
+        } => vec![(*identifier, children.as_ref())],
+        NonTerminal::Congregate(v, _) => v.iter().flat_map(nodes).collect(),
+        _ => vec![],
+    }
+}
+
+fn leaf<'a>(n: &NonTerminal<'a, Syntax>) -> &'a str {
+    match n {
+        NonTerminal::Leaf(s, _) => s,
+        NonTerminal::Node { children, .. } => leaf(children),
+        _ => "",
+    }
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('0') => out.push('\0'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
@@ -0,0 +1,264 @@
+// Operator precedence parsing.
+//
+// `pratt(atom, operators)` parses expressions of atoms and the operators of
+// a table, binding tighter operators first, by precedence climbing: after an
+// operand, an operator is only taken if it binds at least as tightly as the
+// one the operand belongs to, and its right operand is parsed at its own
+// binding power. Each binding power p is split into a left and a right
+// power, 2p and 2p + 1, the larger on the right for left associative
+// operators and on the left for right associative ones, so that operators
+// of the same power chain the right way:
+//
+// 1 - 2 - 3 * 4 ^ 5 ^ 6  =>  ((1 - 2) - (3 * (4 ^ (5 ^ 6))))
+//
+// Like repetitions, an operator whose operand fails is left to what comes
+// after the expression, unless a cut committed the failure.
+
+use std::fmt::Debug;
+
+use crate::core::{
+    ErrorBacktrace, Identifier, Input, NonTerminal, ParseError, ParseResult, Parser, Span, State,
+};
+use crate::memo::apply;
+
+/// How operators of the same binding power chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+    /// `a ^ b ^ c` is `a ^ (b ^ c)`.
+    Right,
+    /// `a < b < c` is an error.
+    None,
+}
+
+// an operator that matched, its tree and the state after it
+type Found<'a, T, S> = Option<(&'a Operator<T, S>, NonTerminal<'a, T, S>, State<'a, S>)>;
+type Failure<'a, T, S> = (ParseError<'a, T, S>, State<'a, S>);
+
+struct Operator<T, S: ?Sized + Input> {
+    parser: Box<dyn Parser<T, S>>,
+    label: T,
+    power: usize,
+    assoc: Assoc,
+}
+
+/// A table of operators for `pratt`. Operators of each kind are tried in
+/// the order they were added, so a longer one sharing a start with a shorter
+/// one goes first. A higher binding power binds tighter.
+pub struct Operators<T, S: ?Sized + Input = str> {
+    prefix: Vec<Operator<T, S>>,
+    infix: Vec<Operator<T, S>>,
+    postfix: Vec<Operator<T, S>>,
+}
+impl<T: Identifier, S: ?Sized + Input> Operators<T, S> {
+    pub fn new() -> Self {
+        Operators {
+            prefix: Vec::new(),
+            infix: Vec::new(),
+            postfix: Vec::new(),
+        }
+    }
+    pub fn prefix(mut self, op: Box<dyn Parser<T, S>>, label: T, power: usize) -> Self {
+        self.prefix.push(Operator {
+            parser: op,
+            label,
+            power,
+            assoc: Assoc::Right,
+        });
+        self
+    }
+    pub fn infix(
+        mut self,
+        op: Box<dyn Parser<T, S>>,
+        label: T,
+        power: usize,
+        assoc: Assoc,
+    ) -> Self {
+        self.infix.push(Operator {
+            parser: op,
+            label,
+            power,
+            assoc,
+        });
+        self
+    }
+    pub fn postfix(mut self, op: Box<dyn Parser<T, S>>, label: T, power: usize) -> Self {
+        self.postfix.push(Operator {
+            parser: op,
+            label,
+            power,
+            assoc: Assoc::Left,
+        });
+        self
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Default for Operators<T, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses expressions of `atom`s and `operators` into nested nodes, each
+/// labelled with its operator's label and holding the operands and the
+/// operator in the order they appear: `1 + 2` gives a node labelled as `+`,
+/// holding the trees of `1`, `+` and `2`.
+pub fn pratt<T: Identifier, S: ?Sized + Input>(
+    atom: Box<dyn Parser<T, S>>,
+    operators: Operators<T, S>,
+) -> Box<dyn Parser<T, S>> {
+    Box::new(ParserPratt { atom, operators })
+}
+
+pub struct ParserPratt<T, S: ?Sized + Input = str> {
+    atom: Box<dyn Parser<T, S>>,
+    operators: Operators<T, S>,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserPratt<T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expression of {:?} with operators", self.atom)?;
+        let ops = &self.operators;
+        for op in ops.prefix.iter().chain(&ops.infix).chain(&ops.postfix) {
+            write!(f, "\n- {:?}", op.parser)?;
+        }
+        Ok(())
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserPratt<T, S> {
+    fn run<'a>(&'a self, input: State<'a, S>) -> ParseResult<'a, T, S> {
+        self.expression(input, 0)
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> ParserPratt<T, S> {
+    // the expression at `input` of the operators binding at least as tightly
+    // as `min`
+    #[allow(clippy::result_large_err)]
+    fn expression<'a>(&'a self, input: State<'a, S>, min: usize) -> ParseResult<'a, T, S> {
+        let (mut lhs, mut head) = self.operand(input)?;
+        // the power of the non associative operator that was applied last
+        let mut chained = None;
+        loop {
+            let mut parts = vec![lhs];
+            let next = self.skip(head, input, &mut parts)?;
+            if let Some((op, n, s)) = self.find(&self.operators.postfix, next, input)? {
+                if 2 * op.power < min {
+                    lhs = parts.swap_remove(0);
+                    break;
+                }
+                parts.push(n);
+                (lhs, head) = (node(op.label, parts, &input, &s), s);
+                chained = None;
+                continue;
+            }
+            let Some((op, n, s)) = self.find(&self.operators.infix, next, input)? else {
+                lhs = parts.swap_remove(0);
+                break;
+            };
+            let (left, right) = match op.assoc {
+                Assoc::Right => (2 * op.power + 1, 2 * op.power),
+                _ => (2 * op.power, 2 * op.power + 1),
+            };
+            if left < min {
+                lhs = parts.swap_remove(0);
+                break;
+            }
+            if op.assoc == Assoc::None && chained == Some(op.power) {
+                return Err((
+                    ParseError {
+                        location: (next.line_of(), next.line, next.column),
+                        head: next.head,
+                        expected: self,
+                        backtrace: ErrorBacktrace::Empty,
+                        msg: Some("Operator is not associative!"),
+                        ctx: input.ctx,
+                    },
+                    State { cut: true, ..input },
+                ));
+            }
+            parts.push(n);
+            let after = self.skip(s, input, &mut parts)?;
+            match self.expression(after, right) {
+                Ok((rhs, s)) => {
+                    parts.push(rhs);
+                    (lhs, head) = (node(op.label, parts, &input, &s), s);
+                    chained = (op.assoc == Assoc::None).then_some(op.power);
+                }
+                Err((e, s)) if s.cut => return Err((e, State { cut: true, ..input })),
+                Err(_) => {
+                    lhs = parts.swap_remove(0);
+                    break;
+                }
+            }
+        }
+        Ok((
+            lhs,
+            State {
+                cut: input.cut || head.cut,
+                ..head
+            },
+        ))
+    }
+    // an atom, or a prefix operator and its operand
+    #[allow(clippy::result_large_err)]
+    fn operand<'a>(&'a self, input: State<'a, S>) -> ParseResult<'a, T, S> {
+        if let Some((op, n, s)) = self.find(&self.operators.prefix, input, input)? {
+            let mut parts = vec![n];
+            let after = self.skip(s, input, &mut parts)?;
+            match self.expression(after, 2 * op.power + 1) {
+                Ok((rhs, s)) => {
+                    parts.push(rhs);
+                    return Ok((node(op.label, parts, &input, &s), s));
+                }
+                Err((e, s)) if s.cut => return Err((e, State { cut: true, ..input })),
+                Err(_) => {}
+            }
+        }
+        apply(self.atom.as_ref(), input)
+    }
+    // the first of `ops` matching at `at`, with its tree and the state after
+    // it; a committed failure fails the expression started at `input`
+    #[allow(clippy::result_large_err)]
+    fn find<'a>(
+        &'a self,
+        ops: &'a [Operator<T, S>],
+        at: State<'a, S>,
+        input: State<'a, S>,
+    ) -> Result<Found<'a, T, S>, Failure<'a, T, S>> {
+        for op in ops {
+            match apply(op.parser.as_ref(), at.uncut()) {
+                Ok((n, s)) => return Ok(Some((op, n, s))),
+                Err((e, s)) if s.cut => return Err((e, State { cut: true, ..input })),
+                Err(_) => {}
+            }
+        }
+        Ok(None)
+    }
+    // skips the trivia at `at`, keeping it in `parts` if asked to
+    #[allow(clippy::result_large_err)]
+    fn skip<'a>(
+        &'a self,
+        at: State<'a, S>,
+        input: State<'a, S>,
+        parts: &mut Vec<NonTerminal<'a, T, S>>,
+    ) -> Result<State<'a, S>, Failure<'a, T, S>> {
+        at.skip_trivia(self, parts)
+            .map_err(|e| (e, State { cut: true, ..input }))
+    }
+}
+
+fn node<'a, T: Identifier, S: ?Sized + Input>(
+    identifier: T,
+    parts: Vec<NonTerminal<'a, T, S>>,
+    from: &State<'a, S>,
+    to: &State<'a, S>,
+) -> NonTerminal<'a, T, S> {
+    let span = Span::between(from, to);
+    NonTerminal::Node {
+        identifier,
+        children: Box::new(NonTerminal::Congregate(parts, span)),
+        span,
+    }
+}
@@ -1,7 +1,15 @@
+use std::borrow::Cow;
 use std::fmt::Debug;
 use std::ops::Deref;
 
-use crate::core::{ErrorBacktrace, Identifier, NonTerminal, ParseError, Parser, StrState};
+use crate::charset::CharSet;
+use crate::core::{
+    ErrorBacktrace, Identifier, Input, NonTerminal, ParseError, Parser, Span, State, StrState,
+};
+use crate::number::Number;
+use crate::quoted::Quoted;
+use crate::regex::Regex;
+use crate::trivia::Trivia;
 
 pub fn pchar<T: Identifier>(c: char) -> Box<dyn Parser<T>> {
     Box::new(ParserChar(c))
@@ -20,30 +28,34 @@ impl<T: Identifier> Parser<T> for ParserChar {
         &'a self,
         input: StrState<'a>,
     ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        input.reached(input.head + 1);
         if input.is_empty() {
+            input.expected(|| format!("`{}`", self.0.escape_debug()));
             return Err((
                 ParseError {
                     location: (input.line_of(), input.line, input.column),
+                    head: input.head,
                     expected: self,
                     backtrace: ErrorBacktrace::Empty,
                     msg: Some("At end of input!"),
+                    ctx: input.ctx,
                 },
                 input,
             ));
         }
         let c = input.deref().chars().nth(0).unwrap();
         if c == self.0 {
-            Ok((
-                NonTerminal::Leaf(&input.string[input.head..][0..c.len_utf8()]),
-                input.advance(c.len_utf8()),
-            ))
+            Ok(leaf(input, c.len_utf8()))
         } else {
+            input.expected(|| format!("`{}`", self.0.escape_debug()));
             Err((
                 ParseError {
                     location: (input.line_of(), input.line, input.column),
+                    head: input.head,
                     expected: self,
                     backtrace: ErrorBacktrace::Empty,
                     msg: None,
+                    ctx: input.ctx,
                 },
                 input,
             ))
@@ -51,10 +63,10 @@ impl<T: Identifier> Parser<T> for ParserChar {
     }
 }
 
-pub fn pstr<T: Identifier>(s: &'static str) -> Box<dyn Parser<T>> {
-    Box::new(ParserStr(s))
+pub fn pstr<T: Identifier>(s: impl Into<Cow<'static, str>>) -> Box<dyn Parser<T>> {
+    Box::new(ParserStr(s.into()))
 }
-pub struct ParserStr(&'static str);
+pub struct ParserStr(Cow<'static, str>);
 impl Debug for ParserStr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "parse string {:?}", self.0)
@@ -65,29 +77,33 @@ impl<T: Identifier> Parser<T> for ParserStr {
         &'a self,
         input: StrState<'a>,
     ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        input.reached(input.head + self.0.len());
         if input.is_empty() {
+            input.expected(|| format!("`{}`", self.0.escape_debug()));
             return Err((
                 ParseError {
                     location: (input.line_of(), input.line, input.column),
+                    head: input.head,
                     expected: self,
                     backtrace: ErrorBacktrace::Empty,
                     msg: Some("At end of input!"),
+                    ctx: input.ctx,
                 },
                 input,
             ));
         }
-        if input.deref().starts_with(self.0) {
-            Ok((
-                NonTerminal::Leaf(&input.string[input.head..][0..self.0.len()]),
-                input.advance(self.0.len()),
-            ))
+        if input.deref().starts_with(self.0.as_ref()) {
+            Ok(leaf(input, self.0.len()))
         } else {
+            input.expected(|| format!("`{}`", self.0.escape_debug()));
             Err((
                 ParseError {
                     location: (input.line_of(), input.line, input.column),
+                    head: input.head,
                     expected: self,
                     backtrace: ErrorBacktrace::Empty,
                     msg: None,
+                    ctx: input.ctx,
                 },
                 input,
             ))
@@ -103,7 +119,8 @@ pub fn ppredicate<T: Identifier, P: Fn(&str) -> (bool, usize) + 'static>(
 ) -> Box<dyn Parser<T>> {
     Box::new(ParserPredicate(Box::new(p)))
 }
-pub struct ParserPredicate(Box<dyn Fn(&str) -> (bool, usize)>);
+type Predicate = Box<dyn Fn(&str) -> (bool, usize)>;
+pub struct ParserPredicate(Predicate);
 impl Debug for ParserPredicate {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "parse accoding to predicate")
@@ -114,30 +131,36 @@ impl<T: Identifier> Parser<T> for ParserPredicate {
         &'a self,
         input: StrState<'a>,
     ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        input.reached(input.head + 1);
         if input.is_empty() {
+            input.expected(|| "character matching predicate".to_owned());
             return Err((
                 ParseError {
                     location: (input.line_of(), input.line, input.column),
+                    head: input.head,
                     expected: self,
                     backtrace: ErrorBacktrace::Empty,
                     msg: Some("At end of input!"),
+                    ctx: input.ctx,
                 },
                 input,
             ));
         }
         let (p, l) = self.0(input.deref());
+        // the predicate looked at the character after its match to stop there
+        input.reached(input.head + l + 1);
         if p {
-            Ok((
-                NonTerminal::Leaf(&input.string[input.head..][0..l]),
-                input.advance(l),
-            ))
+            Ok(leaf(input, l))
         } else {
+            input.expected(|| "character matching predicate".to_owned());
             Err((
                 ParseError {
                     location: (input.line_of(), input.line, input.column),
+                    head: input.head,
                     expected: self,
                     backtrace: ErrorBacktrace::Empty,
                     msg: None,
+                    ctx: input.ctx,
                 },
                 input,
             ))
@@ -162,22 +185,23 @@ impl<T: Identifier> Parser<T> for ParserAny {
         &'a self,
         input: StrState<'a>,
     ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        input.reached(input.head + 1);
         if input.is_empty() {
+            input.expected(|| "any character".to_owned());
             return Err((
                 ParseError {
                     location: (input.line_of(), input.line, input.column),
+                    head: input.head,
                     expected: self,
                     backtrace: ErrorBacktrace::Empty,
                     msg: Some("At end of input!"),
+                    ctx: input.ctx,
                 },
                 input,
             ));
         }
         let c = input.deref().chars().nth(0).unwrap();
-        return Ok((
-            NonTerminal::Leaf(&input.string[input.head..][0..c.len_utf8()]),
-            input.advance(c.len_utf8()),
-        ));
+        Ok(leaf(input, c.len_utf8()))
     }
     fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
         self
@@ -200,18 +224,34 @@ impl<const X: usize> Debug for ParserExcept<X> {
         Ok(())
     }
 }
+impl<const X: usize> ParserExcept<X> {
+    fn expected(&self, input: StrState<'_>) {
+        input.expected(|| {
+            let but: Vec<String> = self
+                .recipe
+                .iter()
+                .map(|c| format!("`{}`", c.escape_debug()))
+                .collect();
+            format!("any character but {}", but.join(", "))
+        });
+    }
+}
 impl<const X: usize, T: Identifier> Parser<T> for ParserExcept<X> {
     fn run<'a>(
         &'a self,
         input: StrState<'a>,
     ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        input.reached(input.head + 1);
         if input.is_empty() {
+            self.expected(input);
             return Err((
                 ParseError {
                     location: (input.line_of(), input.line, input.column),
+                    head: input.head,
                     expected: self,
                     backtrace: ErrorBacktrace::Empty,
                     msg: Some("At end of input!"),
+                    ctx: input.ctx,
                 },
                 input,
             ));
@@ -224,17 +264,17 @@ impl<const X: usize, T: Identifier> Parser<T> for ParserExcept<X> {
             .unwrap()
         {
             let c = input.deref().chars().nth(0).unwrap().len_utf8();
-            return Ok((
-                NonTerminal::Leaf(&input.string[input.head..][0..c]),
-                input.advance(c),
-            ));
+            return Ok(leaf(input, c));
         }
+        self.expected(input);
         Err((
             ParseError {
                 location: (input.line_of(), input.line, input.column),
+                head: input.head,
                 expected: self,
                 backtrace: ErrorBacktrace::Empty,
                 msg: None,
+                ctx: input.ctx,
             },
             input,
         ))
@@ -260,6 +300,13 @@ impl<const X: usize> Debug for ParserOneOf<X> {
         Ok(())
     }
 }
+impl<const X: usize> ParserOneOf<X> {
+    fn expected(&self, input: StrState<'_>) {
+        for c in self.recipe.iter() {
+            input.expected(|| format!("`{}`", c.escape_debug()));
+        }
+    }
+}
 impl<const X: usize, T: Identifier> Parser<T> for ParserOneOf<X> {
     fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
         self
@@ -268,13 +315,17 @@ impl<const X: usize, T: Identifier> Parser<T> for ParserOneOf<X> {
         &'a self,
         input: StrState<'a>,
     ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        input.reached(input.head + 1);
         if input.is_empty() {
+            self.expected(input);
             return Err((
                 ParseError {
                     location: (input.line_of(), input.line, input.column),
+                    head: input.head,
                     expected: self,
                     backtrace: ErrorBacktrace::Empty,
                     msg: Some("At end of input!"),
+                    ctx: input.ctx,
                 },
                 input,
             ));
@@ -287,19 +338,641 @@ impl<const X: usize, T: Identifier> Parser<T> for ParserOneOf<X> {
             .unwrap()
         {
             let c = input.deref().chars().nth(0).unwrap().len_utf8();
-            return Ok((
-                NonTerminal::Leaf(&input.string[input.head..][0..c]),
-                input.advance(c),
-            ));
+            return Ok(leaf(input, c));
         }
+        self.expected(input);
         Err((
             ParseError {
                 location: (input.line_of(), input.line, input.column),
+                head: input.head,
                 expected: self,
                 backtrace: ErrorBacktrace::Empty,
                 msg: None,
+                ctx: input.ctx,
             },
             input,
         ))
     }
 }
+
+/// Parses one character of `set`.
+pub fn pset<T: Identifier>(set: CharSet) -> Box<dyn Parser<T>> {
+    Box::new(ParserSet(set))
+}
+pub struct ParserSet(CharSet);
+impl Debug for ParserSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse any character in {}", self.0)
+    }
+}
+impl<T: Identifier> Parser<T> for ParserSet {
+    fn run<'a>(
+        &'a self,
+        input: StrState<'a>,
+    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        input.reached(input.head + 1);
+        match input.deref().chars().next() {
+            Some(c) if self.0.contains(c) => Ok(leaf(input, c.len_utf8())),
+            found => {
+                input.expected(|| format!("character in {}", self.0));
+                Err((
+                    ParseError {
+                        location: (input.line_of(), input.line, input.column),
+                        head: input.head,
+                        expected: self,
+                        backtrace: ErrorBacktrace::Empty,
+                        msg: found.is_none().then_some("At end of input!"),
+                        ctx: input.ctx,
+                    },
+                    input,
+                ))
+            }
+        }
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+        self
+    }
+}
+
+/// Parses what the regular expression `pattern` matches at the head. As
+/// with backtracking engines, earlier alternatives win over later ones and
+/// repetitions are greedy unless lazy. See the `regex` module for the
+/// supported syntax.
+///
+/// Panics if `pattern` is invalid, which `Regex::new` can check first.
+pub fn pregex<T: Identifier>(pattern: &str) -> Box<dyn Parser<T>> {
+    match Regex::new(pattern) {
+        Ok(re) => Box::new(ParserRegex(re)),
+        Err(e) => panic!("{} in {:?}", e, pattern),
+    }
+}
+pub struct ParserRegex(Regex);
+impl Debug for ParserRegex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse regex /{}/", self.0.pattern())
+    }
+}
+impl<T: Identifier> Parser<T> for ParserRegex {
+    fn run<'a>(
+        &'a self,
+        input: StrState<'a>,
+    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        let (found, looked) = self.0.run(input.deref(), input.head == 0);
+        input.reached(input.head + looked);
+        match found {
+            Some(l) if l > 0 => Ok(leaf(input, l)),
+            Some(_) => Ok((NonTerminal::Empty(Span::at(&input)), input)),
+            None => {
+                input.expected(|| format!("match of /{}/", self.0.pattern()));
+                Err((
+                    ParseError {
+                        location: (input.line_of(), input.line, input.column),
+                        head: input.head,
+                        expected: self,
+                        backtrace: ErrorBacktrace::Empty,
+                        msg: input.is_empty().then_some("At end of input!"),
+                        ctx: input.ctx,
+                    },
+                    input,
+                ))
+            }
+        }
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+        self
+    }
+}
+
+/// Parses the longest of `literals` that the input starts with, whatever
+/// their order, so that `["=", "=="]` parses `==` whole.
+pub fn pliterals<T: Identifier, L: Into<Cow<'static, str>>>(
+    literals: impl IntoIterator<Item = L>,
+) -> Box<dyn Parser<T>> {
+    Box::new(ParserLiterals::new(
+        literals.into_iter().map(|l| (l.into(), None)),
+    ))
+}
+/// Like `pliterals`, labelling the leaf of each literal with its own
+/// identifier, so that a table of operators becomes a single parser.
+pub fn pliterals_labeled<T: Identifier, L: Into<Cow<'static, str>>>(
+    literals: impl IntoIterator<Item = (L, T)>,
+) -> Box<dyn Parser<T>> {
+    Box::new(ParserLiterals::new(
+        literals.into_iter().map(|(l, t)| (l.into(), Some(t))),
+    ))
+}
+pub struct ParserLiterals<T> {
+    literals: Vec<(Cow<'static, str>, Option<T>)>,
+    // the root is first, children are sorted by character
+    trie: Vec<TrieNode>,
+}
+#[derive(Default)]
+struct TrieNode {
+    children: Vec<(char, usize)>,
+    // the literal ending here
+    literal: Option<usize>,
+}
+impl<T> ParserLiterals<T> {
+    fn new(literals: impl Iterator<Item = (Cow<'static, str>, Option<T>)>) -> Self {
+        let literals: Vec<_> = literals.collect();
+        assert!(!literals.is_empty(), "empty list for pliterals!");
+        let mut trie = vec![TrieNode::default()];
+        for (i, (l, _)) in literals.iter().enumerate() {
+            assert!(!l.is_empty(), "empty literal for pliterals!");
+            let mut node = 0;
+            for c in l.chars() {
+                node = match trie[node].children.binary_search_by_key(&c, |(c, _)| *c) {
+                    Ok(j) => trie[node].children[j].1,
+                    Err(j) => {
+                        trie.push(TrieNode::default());
+                        let child = trie.len() - 1;
+                        trie[node].children.insert(j, (c, child));
+                        child
+                    }
+                };
+            }
+            assert!(
+                trie[node].literal.is_none(),
+                "literal {:?} given twice for pliterals!",
+                l
+            );
+            trie[node].literal = Some(i);
+        }
+        ParserLiterals { literals, trie }
+    }
+    // the longest literal `s` starts with, if any, and how far into `s`
+    // finding it looked
+    fn longest(&self, s: &str) -> (Option<usize>, usize) {
+        let mut node = 0;
+        let mut found = None;
+        for (i, c) in s.char_indices() {
+            let children = &self.trie[node].children;
+            match children.binary_search_by_key(&c, |(c, _)| *c) {
+                Ok(j) => node = children[j].1,
+                Err(_) => return (found, i + 1),
+            }
+            if let Some(l) = self.trie[node].literal {
+                found = Some(l);
+            }
+        }
+        (found, s.len() + 1)
+    }
+}
+impl<T> Debug for ParserLiterals<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse the longest literal of:")?;
+        for (l, _) in self.literals.iter() {
+            write!(f, "\n- {:?}", l)?;
+        }
+        Ok(())
+    }
+}
+impl<T: Identifier> Parser<T> for ParserLiterals<T> {
+    fn run<'a>(
+        &'a self,
+        input: StrState<'a>,
+    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        let (found, looked) = self.longest(input.deref());
+        input.reached(input.head + looked);
+        let Some(i) = found else {
+            for (l, _) in self.literals.iter() {
+                input.expected(|| format!("`{}`", l.escape_debug()));
+            }
+            return Err((
+                ParseError {
+                    location: (input.line_of(), input.line, input.column),
+                    head: input.head,
+                    expected: self,
+                    backtrace: ErrorBacktrace::Empty,
+                    msg: input.is_empty().then_some("At end of input!"),
+                    ctx: input.ctx,
+                },
+                input,
+            ));
+        };
+        let (l, s) = leaf(input, self.literals[i].0.len());
+        match self.literals[i].1 {
+            Some(identifier) => Ok((
+                NonTerminal::Node {
+                    identifier,
+                    span: l.span(),
+                    children: Box::new(l),
+                },
+                s,
+            )),
+            None => Ok((l, s)),
+        }
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+        self
+    }
+}
+
+/// Parses a numeric literal of `syntax` into its leaf. A literal that
+/// starts well but breaks off, such as `1e` or `0x`, fails where it breaks
+/// off with what went wrong. `number::integer` and `number::float` also give
+/// its value.
+pub fn pnumber<T: Identifier>(syntax: Number) -> Box<dyn Parser<T>> {
+    Box::new(ParserNumber(syntax))
+}
+pub struct ParserNumber(Number);
+impl Debug for ParserNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse number of {:?}", self.0)
+    }
+}
+impl<T: Identifier> Parser<T> for ParserNumber {
+    fn run<'a>(
+        &'a self,
+        input: StrState<'a>,
+    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        let (at, msg) = match self.0.scan(input.deref()) {
+            Ok(l) => {
+                input.reached(input.head + l + 1);
+                return Ok(leaf(input, l));
+            }
+            Err((0, _, _)) => {
+                input.expected(|| "number".to_owned());
+                (0, input.is_empty().then_some("At end of input!"))
+            }
+            Err((at, msg, digit)) => {
+                input.advance(at).expected(|| digit.to_owned());
+                (at, Some(msg))
+            }
+        };
+        input.reached(input.head + at + 1);
+        let end = input.advance(at);
+        Err((
+            ParseError {
+                location: (end.line_of(), end.line, end.column),
+                head: end.head,
+                expected: self,
+                backtrace: ErrorBacktrace::Empty,
+                msg,
+                ctx: input.ctx,
+            },
+            input,
+        ))
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+        self
+    }
+}
+
+/// Parses a string literal of `syntax`, quotes included, into its leaf. A
+/// bad escape fails at its backslash, and a string left open at its opening
+/// quote. `quoted::string` also decodes it.
+pub fn pquoted<T: Identifier>(syntax: Quoted) -> Box<dyn Parser<T>> {
+    Box::new(ParserQuoted(syntax))
+}
+pub struct ParserQuoted(Quoted);
+impl Debug for ParserQuoted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse string of {:?}", self.0)
+    }
+}
+impl<T: Identifier> Parser<T> for ParserQuoted {
+    fn run<'a>(
+        &'a self,
+        input: StrState<'a>,
+    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        let (at, msg) = match self.0.scan(input.deref()) {
+            Some(Ok((l, _))) => {
+                input.reached(input.head + l);
+                return Ok(leaf(input, l));
+            }
+            Some(Err((at, msg))) => {
+                // an open string looked up to the end of the input
+                let looked = if at == 0 { input.len() } else { at };
+                input.reached(input.head + looked + 1);
+                (at, Some(msg))
+            }
+            None => {
+                input.reached(input.head + 1);
+                input.expected(|| "string".to_owned());
+                (0, input.is_empty().then_some("At end of input!"))
+            }
+        };
+        let end = input.advance(at);
+        Err((
+            ParseError {
+                location: (end.line_of(), end.line, end.column),
+                head: end.head,
+                expected: self,
+                backtrace: ErrorBacktrace::Empty,
+                msg,
+                ctx: input.ctx,
+            },
+            input,
+        ))
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+        self
+    }
+}
+
+/// Parses the trivia at the head, possibly none, into a leaf, for
+/// `skipping()`. An unclosed block comment fails for good, at its start.
+pub fn ptrivia<T: Identifier>(trivia: Trivia) -> Box<dyn Parser<T>> {
+    Box::new(ParserTrivia(trivia))
+}
+pub struct ParserTrivia(Trivia);
+impl Debug for ParserTrivia {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse trivia of {:?}", self.0)
+    }
+}
+impl<T: Identifier> Parser<T> for ParserTrivia {
+    fn run<'a>(
+        &'a self,
+        input: StrState<'a>,
+    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        match self.0.scan(input.deref()) {
+            Ok(0) => {
+                input.reached(input.head + 1);
+                Ok((NonTerminal::Empty(Span::at(&input)), input))
+            }
+            Ok(l) => {
+                input.reached(input.head + l + 1);
+                Ok(leaf(input, l))
+            }
+            Err(at) => {
+                input.reached(input.head + input.len() + 1);
+                let open = input.advance(at);
+                Err((
+                    ParseError {
+                        location: (open.line_of(), open.line, open.column),
+                        head: open.head,
+                        expected: self,
+                        backtrace: ErrorBacktrace::Empty,
+                        msg: Some("Unclosed comment!"),
+                        ctx: input.ctx,
+                    },
+                    State { cut: true, ..input },
+                ))
+            }
+        }
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+        self
+    }
+}
+
+/// Parses one item of any input for which `p` holds: a byte, a token or a
+/// character.
+pub fn pitem<T: Identifier, S: ?Sized + Input, P: Fn(&S::Item) -> bool + 'static>(
+    p: P,
+) -> Box<dyn Parser<T, S>> {
+    Box::new(ParserItem(Box::new(p)))
+}
+type ItemPredicate<S> = Box<dyn Fn(&<S as Input>::Item) -> bool>;
+pub struct ParserItem<S: ?Sized + Input>(ItemPredicate<S>);
+impl<S: ?Sized + Input> Debug for ParserItem<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse an item according to predicate")
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserItem<S> {
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        input.reached(input.head + 1);
+        match input.deref().first() {
+            Some((item, l)) if self.0(&item) => Ok(leaf(input, l)),
+            found => {
+                input.expected(|| "item matching predicate".to_owned());
+                Err((
+                    ParseError {
+                        location: (input.line_of(), input.line, input.column),
+                        head: input.head,
+                        expected: self,
+                        backtrace: ErrorBacktrace::Empty,
+                        msg: found.is_none().then_some("At end of input!"),
+                        ctx: input.ctx,
+                    },
+                    input,
+                ))
+            }
+        }
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+}
+
+/// Parses exactly `tag`, a run of bytes, tokens or text.
+pub fn ptag<T: Identifier, S: ?Sized + Input>(tag: &'static S) -> Box<dyn Parser<T, S>> {
+    assert!(!tag.is_empty(), "empty tag for ptag!");
+    Box::new(ParserTag(tag))
+}
+pub struct ParserTag<S: ?Sized + Input + 'static>(&'static S);
+impl<S: ?Sized + Input> Debug for ParserTag<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse {:?}", self.0)
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserTag<S> {
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        input.reached(input.head + self.0.len());
+        if input.deref().starts_with(self.0) {
+            let l = self.0.len();
+            Ok(leaf(input, l))
+        } else {
+            input.expected(|| format!("`{:?}`", self.0));
+            Err((
+                ParseError {
+                    location: (input.line_of(), input.line, input.column),
+                    head: input.head,
+                    expected: self,
+                    backtrace: ErrorBacktrace::Empty,
+                    msg: None,
+                    ctx: input.ctx,
+                },
+                input,
+            ))
+        }
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+}
+
+/// Parses `c` in any case, such as `é` or `É`. The leaf is the input as
+/// written.
+pub fn pchar_nocase<T: Identifier>(c: char) -> Box<dyn Parser<T>> {
+    Box::new(ParserNoCase::new(c.to_string()))
+}
+/// Parses `s` in any case, comparing characters by Unicode case folding, so
+/// that `"straße"` also matches `"STRASSE"`. The leaf is the input as
+/// written.
+pub fn pstr_nocase<T: Identifier>(s: impl Into<Cow<'static, str>>) -> Box<dyn Parser<T>> {
+    let s = s.into();
+    assert!(!s.is_empty(), "empty string for pstr_nocase!");
+    Box::new(ParserNoCase::new(s))
+}
+pub struct ParserNoCase {
+    literal: Cow<'static, str>,
+    folded: Vec<char>,
+}
+impl ParserNoCase {
+    fn new(literal: impl Into<Cow<'static, str>>) -> Self {
+        let literal = literal.into();
+        let folded = literal.chars().flat_map(fold).collect();
+        ParserNoCase { literal, folded }
+    }
+}
+impl Debug for ParserNoCase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse string {:?} in any case", self.literal)
+    }
+}
+impl<T: Identifier> Parser<T> for ParserNoCase {
+    fn run<'a>(
+        &'a self,
+        input: StrState<'a>,
+    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        let (found, looked) = prefix_nocase(input.deref(), &self.folded);
+        input.reached(input.head + looked);
+        match found {
+            Some(l) => Ok(leaf(input, l)),
+            None => {
+                input.expected(|| format!("`{}`", self.literal.escape_debug()));
+                Err((
+                    ParseError {
+                        location: (input.line_of(), input.line, input.column),
+                        head: input.head,
+                        expected: self,
+                        backtrace: ErrorBacktrace::Empty,
+                        msg: input.is_empty().then_some("At end of input!"),
+                        ctx: input.ctx,
+                    },
+                    input,
+                ))
+            }
+        }
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+        self
+    }
+}
+
+/// Parses the longest of `words` in any case, as a whole word: it must not
+/// be followed by a letter, a digit or `_`, so that `select` is no keyword
+/// in `selection`.
+pub fn pkeyword<T: Identifier>(
+    words: impl IntoIterator<Item = &'static str>,
+) -> Box<dyn Parser<T>> {
+    let words: Vec<_> = words.into_iter().collect();
+    assert!(!words.is_empty(), "empty list for pkeyword!");
+    assert!(
+        words.iter().all(|w| !w.is_empty()),
+        "empty keyword for pkeyword!"
+    );
+    Box::new(ParserKeyword {
+        folded: words
+            .iter()
+            .map(|w| w.chars().flat_map(fold).collect())
+            .collect(),
+        words,
+    })
+}
+pub struct ParserKeyword {
+    words: Vec<&'static str>,
+    folded: Vec<Vec<char>>,
+}
+impl Debug for ParserKeyword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse any keyword of:")?;
+        for w in self.words.iter() {
+            write!(f, "\n- {:?}", w)?;
+        }
+        Ok(())
+    }
+}
+impl<T: Identifier> Parser<T> for ParserKeyword {
+    fn run<'a>(
+        &'a self,
+        input: StrState<'a>,
+    ) -> Result<(NonTerminal<'a, T>, StrState<'a>), (ParseError<'a, T>, StrState<'a>)> {
+        let rest = input.deref();
+        let mut longest = None;
+        for folded in self.folded.iter() {
+            let (found, looked) = prefix_nocase(rest, folded);
+            input.reached(input.head + looked);
+            let Some(l) = found else { continue };
+            // the character after the word decides whether it is whole
+            input.reached(input.head + l + 1);
+            let whole = match rest[l..].chars().next() {
+                Some(c) => !(c.is_alphanumeric() || c == '_'),
+                None => true,
+            };
+            if whole && longest < Some(l) {
+                longest = Some(l);
+            }
+        }
+        if let Some(l) = longest {
+            return Ok(leaf(input, l));
+        }
+        for w in self.words.iter() {
+            input.expected(|| format!("`{}`", w.escape_debug()));
+        }
+        Err((
+            ParseError {
+                location: (input.line_of(), input.line, input.column),
+                head: input.head,
+                expected: self,
+                backtrace: ErrorBacktrace::Empty,
+                msg: input.is_empty().then_some("At end of input!"),
+                ctx: input.ctx,
+            },
+            input,
+        ))
+    }
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T>> {
+        self
+    }
+}
+
+// `c` with its case folded away, possibly as several characters, such as
+// `ss` for `ß`
+fn fold(c: char) -> impl Iterator<Item = char> {
+    c.to_uppercase().flat_map(char::to_lowercase)
+}
+
+// the length of the prefix of `s` that folds to `folded`, if any, and how
+// far into `s` finding out looked
+fn prefix_nocase(s: &str, folded: &[char]) -> (Option<usize>, usize) {
+    let mut at = 0;
+    for (i, c) in s.char_indices() {
+        let end = i + c.len_utf8();
+        for f in fold(c) {
+            if folded.get(at) != Some(&f) {
+                return (None, end);
+            }
+            at += 1;
+        }
+        if at == folded.len() {
+            return (Some(end), end);
+        }
+    }
+    (None, s.len() + 1)
+}
+
+// the item of `n` positions at `input`, and the state after it
+fn leaf<T: Identifier, S: ?Sized + Input>(
+    input: State<'_, S>,
+    n: usize,
+) -> (NonTerminal<'_, T, S>, State<'_, S>) {
+    let s = input.advance(n);
+    (
+        NonTerminal::Leaf(
+            input.string.slice(input.head, s.head),
+            Span::between(&input, &s),
+        ),
+        s,
+    )
+}
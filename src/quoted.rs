@@ -0,0 +1,168 @@
+// Quoted string literals.
+//
+// `Quoted` describes the syntax of the string literals a grammar accepts,
+// which `pquoted` parses and `string` decodes:
+//
+// string <- quote (escape / !quote .)* quote   the same quote at both ends
+// escape <- '\' (simple / 'x' hex hex / 'u{' hex+ '}')
+//
+// The simple escapes come from a table, `\n` for a newline and the like.
+// With `no_escapes` backslashes are plain characters, so strings end at the
+// first closing quote. With `doubled`, as in SQL, a quote written twice stands for
+// itself.
+
+use crate::core::Identifier;
+use crate::primitives::pquoted;
+use crate::typed::{leaf, Semantic, Typed};
+
+type Scan = Option<Result<(usize, String), (usize, &'static str)>>;
+
+/// The syntax of string literals. By default they are double quoted, with
+/// the escapes `\n`, `\r`, `\t`, `\0`, `\\`, `\"` and `\'`, along with `\x41`
+/// and `\u{1F600}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quoted {
+    quotes: Vec<char>,
+    // the character after the backslash, and what it stands for
+    escapes: Vec<(char, char)>,
+    hex: bool,
+    unicode: bool,
+    no_escapes: bool,
+    doubled: bool,
+}
+impl Quoted {
+    pub fn new() -> Self {
+        Quoted {
+            quotes: vec!['"'],
+            escapes: vec![
+                ('n', '\n'),
+                ('r', '\r'),
+                ('t', '\t'),
+                ('0', '\0'),
+                ('\\', '\\'),
+                ('"', '"'),
+                ('\'', '\''),
+            ],
+            hex: true,
+            unicode: true,
+            no_escapes: false,
+            doubled: false,
+        }
+    }
+    /// The characters that may quote a string. It ends with the one it
+    /// started with.
+    pub fn quotes(mut self, quotes: impl IntoIterator<Item = char>) -> Self {
+        self.quotes = quotes.into_iter().collect();
+        assert!(!self.quotes.is_empty(), "no quotes for Quoted!");
+        self
+    }
+    /// Replaces the simple escapes with `escapes`, each the character after
+    /// the backslash and what the escape stands for.
+    pub fn escapes(mut self, escapes: impl IntoIterator<Item = (char, char)>) -> Self {
+        self.escapes = escapes.into_iter().collect();
+        self
+    }
+    /// `\x` followed by two hexadecimal digits.
+    pub fn hex(mut self, on: bool) -> Self {
+        self.hex = on;
+        self
+    }
+    /// `\u{...}` with up to six hexadecimal digits.
+    pub fn unicode(mut self, on: bool) -> Self {
+        self.unicode = on;
+        self
+    }
+    /// Strings without escapes, where a backslash is a plain character. There
+    /// is no `#` around the quotes as in Rust raw strings, so a quote can only
+    /// be part of such a string with `doubled`.
+    pub fn no_escapes(mut self, on: bool) -> Self {
+        self.no_escapes = on;
+        self
+    }
+    /// The closing quote written twice, `'it''s'`, stands for one quote.
+    pub fn doubled(mut self, on: bool) -> Self {
+        self.doubled = on;
+        self
+    }
+
+    /// The length and the decoded content of the string `s` starts with, or
+    /// where in `s` and why it is malformed. `None` if `s` does not start
+    /// with a quote.
+    pub(crate) fn scan(&self, s: &str) -> Scan {
+        let quote = s.chars().next().filter(|c| self.quotes.contains(c))?;
+        let mut decoded = String::new();
+        let mut chars = s.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            if c == quote {
+                if self.doubled && s[i + c.len_utf8()..].starts_with(quote) {
+                    chars.next();
+                    decoded.push(quote);
+                    continue;
+                }
+                return Some(Ok((i + c.len_utf8(), decoded)));
+            }
+            if c != '\\' || self.no_escapes {
+                decoded.push(c);
+                continue;
+            }
+            let escaped = match chars.next() {
+                Some((_, 'x')) if self.hex => {
+                    let digits = s
+                        .get(i + 2..i + 4)
+                        .filter(|d| d.chars().all(|c| c.is_ascii_hexdigit()));
+                    chars.nth(1);
+                    digits
+                        .and_then(|d| u32::from_str_radix(d, 16).ok())
+                        .and_then(char::from_u32)
+                        .ok_or("Invalid `\\x` escape, expected two hexadecimal digits!")
+                }
+                Some((_, 'u')) if self.unicode => {
+                    let digits = s[i + 2..]
+                        .strip_prefix('{')
+                        .and_then(|d| d.split_once('}'))
+                        .map(|(d, _)| d)
+                        .filter(|d| (1..=6).contains(&d.len()))
+                        .filter(|d| d.chars().all(|c| c.is_ascii_hexdigit()));
+                    match digits {
+                        Some(d) => {
+                            chars.nth(d.len() + 1);
+                            u32::from_str_radix(d, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or("Invalid `\\u` escape, not a character!")
+                        }
+                        None => Err("Invalid `\\u` escape, expected `{` and hexadecimal digits!"),
+                    }
+                }
+                Some((_, e)) => match self.escapes.iter().find(|(c, _)| *c == e) {
+                    Some((_, d)) => Ok(*d),
+                    None => Err("Unknown escape!"),
+                },
+                None => break,
+            };
+            match escaped {
+                Ok(c) => decoded.push(c),
+                Err(msg) => return Some(Err((i, msg))),
+            }
+        }
+        Some(Err((0, "Unterminated string!")))
+    }
+    /// The content of `text`, a string literal of this syntax, with its
+    /// escapes decoded.
+    pub fn decode(&self, text: &str) -> String {
+        match self.scan(text) {
+            Some(Ok((_, decoded))) => decoded,
+            _ => panic!("{:?} is not a string literal for Quoted!", text),
+        }
+    }
+}
+impl Default for Quoted {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses a string literal of `syntax` into its decoded content.
+pub fn string<T: Identifier>(syntax: Quoted) -> Typed<T, String> {
+    pquoted(syntax.clone()).map(move |n| syntax.decode(leaf(&n)))
+}
@@ -0,0 +1,464 @@
+// Regular expressions.
+//
+// A pattern is parsed into a tree, then compiled into a program for a Pike
+// VM: a set of threads walks the input one character at a time, all in step,
+// so matching takes time linear in the input whatever the pattern. Threads
+// are kept in order of preference, which gives the usual semantics of
+// backtracking engines: the first alternative that matches wins, and
+// repetitions take as much as they can unless they are lazy.
+//
+// Supported syntax:
+//
+//   a \. \n \t \r \0     characters, escaped when special
+//   .                    any character but a newline
+//   [a-z_] [^"\\]        classes and negated classes
+//   \d \w \s \D \W \S    ASCII digits, ASCII word characters, whitespace
+//   (a|b) (?:a|b)        groups and alternatives
+//   * + ? {n} {n,} {n,m} repetitions, lazy when followed by `?`
+//   ^ $                  start and end of the input
+
+use crate::charset::CharSet;
+
+/// An invalid pattern, with the byte offset in it at which the problem
+/// was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexError {
+    pub offset: usize,
+    pub msg: &'static str,
+}
+impl std::fmt::Display for RegexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}]\tInvalid regex: {}", self.offset, self.msg)
+    }
+}
+impl std::error::Error for RegexError {}
+
+// repetitions are unrolled, so their counts are bounded, and so is the
+// size of the program they unroll into, which nesting multiplies
+const MAX_REPEAT: u32 = 1000;
+const MAX_PROGRAM: usize = 100_000;
+const TOO_LARGE: &str = "Pattern is too large once its repetitions are unrolled!";
+
+/// A compiled regular expression, matched at the start of the input.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    pattern: String,
+    program: Vec<Inst>,
+}
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, RegexError> {
+        let mut parser = Syntax {
+            pattern,
+            at: 0,
+            depth: 0,
+        };
+        let tree = parser.alternation()?;
+        if tree.size() > MAX_PROGRAM {
+            return Err(RegexError {
+                offset: 0,
+                msg: TOO_LARGE,
+            });
+        }
+        let mut program = Vec::new();
+        compile(&tree, &mut program);
+        program.push(Inst::Match);
+        Ok(Regex {
+            pattern: pattern.to_owned(),
+            program,
+        })
+    }
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+    /// The length of the match at the start of `s`, if any. `at_start` tells
+    /// whether `s` starts at the start of the input, for `^`.
+    pub fn prefix(&self, s: &str, at_start: bool) -> Option<usize> {
+        self.run(s, at_start).0
+    }
+    // the match, and how far into `s` finding it looked
+    pub(crate) fn run(&self, s: &str, at_start: bool) -> (Option<usize>, usize) {
+        let mut found = None;
+        let mut looked = 0;
+        let mut seen = vec![usize::MAX; self.program.len()];
+        let mut threads = Vec::new();
+        self.follow(0, 0, s, at_start, &mut seen, &mut threads);
+        let mut pos = 0;
+        while !threads.is_empty() {
+            looked = pos + 1;
+            let c = s[pos..].chars().next();
+            let next_pos = pos + c.map_or(1, char::len_utf8);
+            let mut next = Vec::new();
+            for &pc in threads.iter() {
+                match &self.program[pc] {
+                    Inst::Match => {
+                        // threads after this one are less preferred
+                        found = Some(pos);
+                        break;
+                    }
+                    Inst::Char(set) => {
+                        if c.is_some_and(|c| set.contains(c)) {
+                            self.follow(pc + 1, next_pos, s, at_start, &mut seen, &mut next);
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            if c.is_none() {
+                break;
+            }
+            threads = next;
+            pos = next_pos;
+        }
+        (found, looked)
+    }
+    // adds the thread at `pc` to `threads`, following jumps, splits and
+    // assertions in order of preference
+    fn follow(
+        &self,
+        pc: usize,
+        pos: usize,
+        s: &str,
+        at_start: bool,
+        seen: &mut [usize],
+        threads: &mut Vec<usize>,
+    ) {
+        if seen[pc] == pos {
+            return;
+        }
+        seen[pc] = pos;
+        match self.program[pc] {
+            Inst::Jump(to) => self.follow(to, pos, s, at_start, seen, threads),
+            Inst::Split(first, second) => {
+                self.follow(first, pos, s, at_start, seen, threads);
+                self.follow(second, pos, s, at_start, seen, threads);
+            }
+            Inst::Start => {
+                if pos == 0 && at_start {
+                    self.follow(pc + 1, pos, s, at_start, seen, threads);
+                }
+            }
+            Inst::End => {
+                if pos == s.len() {
+                    self.follow(pc + 1, pos, s, at_start, seen, threads);
+                }
+            }
+            _ => threads.push(pc),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(CharSet),
+    // try the first branch before the second
+    Split(usize, usize),
+    Jump(usize),
+    Start,
+    End,
+    Match,
+}
+
+enum Tree {
+    Set(CharSet),
+    Concat(Vec<Tree>),
+    Alternation(Vec<Tree>),
+    Repeat {
+        tree: Box<Tree>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+    Start,
+    End,
+}
+impl Tree {
+    // the number of instructions `compile` turns this into, saturating
+    fn size(&self) -> usize {
+        match self {
+            Tree::Set(_) | Tree::Start | Tree::End => 1,
+            Tree::Concat(v) => v.iter().fold(0, |n, t| n.saturating_add(t.size())),
+            Tree::Alternation(v) => v
+                .iter()
+                .fold(2 * (v.len() - 1), |n, t| n.saturating_add(t.size())),
+            Tree::Repeat { tree, min, max, .. } => {
+                let body = tree.size();
+                let optional = match max {
+                    None => body.saturating_add(2),
+                    Some(max) => body.saturating_add(1).saturating_mul((max - min) as usize),
+                };
+                body.saturating_mul(*min as usize).saturating_add(optional)
+            }
+        }
+    }
+}
+
+fn compile(tree: &Tree, program: &mut Vec<Inst>) {
+    match tree {
+        Tree::Set(set) => program.push(Inst::Char(set.clone())),
+        Tree::Concat(v) => v.iter().for_each(|t| compile(t, program)),
+        Tree::Alternation(v) => {
+            let mut jumps = Vec::new();
+            for (i, t) in v.iter().enumerate() {
+                if i + 1 == v.len() {
+                    compile(t, program);
+                    break;
+                }
+                let split = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                compile(t, program);
+                jumps.push(program.len());
+                program.push(Inst::Jump(0));
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+            for j in jumps {
+                program[j] = Inst::Jump(program.len());
+            }
+        }
+        Tree::Repeat {
+            tree,
+            min,
+            max,
+            greedy,
+        } => {
+            for _ in 0..*min {
+                compile(tree, program);
+            }
+            let split = |body, rest| match greedy {
+                true => Inst::Split(body, rest),
+                false => Inst::Split(rest, body),
+            };
+            match max {
+                None => {
+                    let start = program.len();
+                    program.push(Inst::Jump(0));
+                    compile(tree, program);
+                    program.push(Inst::Jump(start));
+                    program[start] = split(start + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Jump(0));
+                        compile(tree, program);
+                    }
+                    for s in splits {
+                        program[s] = split(s + 1, program.len());
+                    }
+                }
+            }
+        }
+        Tree::Start => program.push(Inst::Start),
+        Tree::End => program.push(Inst::End),
+    }
+}
+
+// a recursive descent parser for patterns
+struct Syntax<'p> {
+    pattern: &'p str,
+    at: usize,
+    depth: usize,
+}
+impl Syntax<'_> {
+    fn error(&self, msg: &'static str) -> RegexError {
+        RegexError {
+            offset: self.at,
+            msg,
+        }
+    }
+    fn peek(&self) -> Option<char> {
+        self.pattern[self.at..].chars().next()
+    }
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.at += c.len_utf8();
+        Some(c)
+    }
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.at += c.len_utf8();
+        }
+        found
+    }
+    fn alternation(&mut self) -> Result<Tree, RegexError> {
+        let mut alternatives = vec![self.concat()?];
+        while self.eat('|') {
+            alternatives.push(self.concat()?);
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.pop().unwrap(),
+            _ => Tree::Alternation(alternatives),
+        })
+    }
+    fn concat(&mut self) -> Result<Tree, RegexError> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || (c == ')' && self.depth > 0) {
+                break;
+            }
+            let atom = self.atom()?;
+            items.push(self.repeat(atom)?);
+        }
+        Ok(Tree::Concat(items))
+    }
+    fn repeat(&mut self, mut tree: Tree) -> Result<Tree, RegexError> {
+        loop {
+            let start = self.at;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => {
+                    self.next();
+                    let min = self.number()?;
+                    let max = match self.eat(',') {
+                        true if self.peek() == Some('}') => None,
+                        true => Some(self.number()?),
+                        false => Some(min),
+                    };
+                    if self.peek() != Some('}') {
+                        return Err(self.error("Unclosed repetition!"));
+                    }
+                    if max.is_some_and(|max| max < min) {
+                        self.at = start;
+                        return Err(self.error("Repetition has its bounds reversed!"));
+                    }
+                    (min, max)
+                }
+                _ => return Ok(tree),
+            };
+            if matches!(tree, Tree::Start | Tree::End) {
+                return Err(self.error("Repetition of an anchor!"));
+            }
+            self.next();
+            let greedy = !self.eat('?');
+            tree = Tree::Repeat {
+                tree: Box::new(tree),
+                min,
+                max,
+                greedy,
+            };
+            if tree.size() > MAX_PROGRAM {
+                self.at = start;
+                return Err(self.error(TOO_LARGE));
+            }
+        }
+    }
+    fn number(&mut self) -> Result<u32, RegexError> {
+        let start = self.at;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.next();
+        }
+        match self.pattern[start..self.at].parse() {
+            Ok(n) if n <= MAX_REPEAT => Ok(n),
+            Ok(_) => Err(self.error("Repetition count is too large!")),
+            Err(_) => Err(self.error("Expected a repetition count!")),
+        }
+    }
+    fn atom(&mut self) -> Result<Tree, RegexError> {
+        let start = self.at;
+        let c = self.next().unwrap();
+        Ok(match c {
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err(self.error("Unknown group kind!"));
+                }
+                self.depth += 1;
+                let tree = self.alternation()?;
+                self.depth -= 1;
+                if !self.eat(')') {
+                    self.at = start;
+                    return Err(self.error("Unclosed group!"));
+                }
+                tree
+            }
+            '[' => Tree::Set(self.class(start)?),
+            '.' => Tree::Set(CharSet::chars(['\n']).negate()),
+            '^' => Tree::Start,
+            '$' => Tree::End,
+            '\\' => Tree::Set(self.escape()?),
+            '*' | '+' | '?' | '{' => {
+                self.at = start;
+                return Err(self.error("Repetition of nothing!"));
+            }
+            ')' | ']' | '}' => {
+                self.at = start;
+                return Err(self.error("Unescaped closing bracket!"));
+            }
+            c => Tree::Set(CharSet::chars([c])),
+        })
+    }
+    // after a backslash
+    fn escape(&mut self) -> Result<CharSet, RegexError> {
+        let word = || CharSet::ascii_alphanumeric().union(&CharSet::chars(['_']));
+        Ok(match self.next() {
+            Some('d') => CharSet::ascii_digit(),
+            Some('D') => CharSet::ascii_digit().negate(),
+            Some('w') => word(),
+            Some('W') => word().negate(),
+            Some('s') => CharSet::whitespace(),
+            Some('S') => CharSet::whitespace().negate(),
+            Some('n') => CharSet::chars(['\n']),
+            Some('t') => CharSet::chars(['\t']),
+            Some('r') => CharSet::chars(['\r']),
+            Some('0') => CharSet::chars(['\0']),
+            Some(c) if !c.is_alphanumeric() => CharSet::chars([c]),
+            Some(_) => return Err(self.error("Unknown escape!")),
+            None => return Err(self.error("Pattern ends with a backslash!")),
+        })
+    }
+    // after `[`, which is at `start`
+    fn class(&mut self, start: usize) -> Result<CharSet, RegexError> {
+        let negated = self.eat('^');
+        let mut set = CharSet::new();
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                None => {
+                    self.at = start;
+                    return Err(self.error("Unclosed class!"));
+                }
+                Some(']') if !first => break,
+                Some('\\') => {
+                    let escaped = self.escape()?;
+                    match escaped.ranges().collect::<Vec<_>>()[..] {
+                        [(c, d)] if c == d => c,
+                        _ => {
+                            set = set.union(&escaped);
+                            first = false;
+                            continue;
+                        }
+                    }
+                }
+                Some(c) => c,
+            };
+            first = false;
+            let rest = &self.pattern[self.at..];
+            if rest.starts_with('-') && !rest.starts_with("-]") && rest.len() > 1 {
+                self.next();
+                let to = match self.next() {
+                    Some('\\') => {
+                        let escaped = self.escape()?;
+                        match escaped.ranges().collect::<Vec<_>>()[..] {
+                            [(c, d)] if c == d => c,
+                            _ => return Err(self.error("Range ends with a class!")),
+                        }
+                    }
+                    Some(d) => d,
+                    None => unreachable!(),
+                };
+                if to < c {
+                    return Err(self.error("Range has its bounds reversed!"));
+                }
+                set = set.union(&CharSet::range(c, to));
+            } else {
+                set = set.union(&CharSet::chars([c]));
+            }
+        }
+        Ok(match negated {
+            true => set.negate(),
+            false => set,
+        })
+    }
+}
@@ -0,0 +1,77 @@
+use std::cell::OnceCell;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::core::{Identifier, Input, NonTerminal, ParseError, Parser, State};
+use crate::memo::apply;
+
+/// A handle to a parser whose body is defined later, which allows rules to
+/// refer to themselves or to each other. `parser()` hands out references
+/// that can be used in combinators right away, and `define()` sets the body
+/// once it has been built:
+/// `expr.define(pchar('(').seq(expr.parser()).seq(pchar(')')).or(num))`.
+///
+/// When the input carries a `Context`, rules are memoized and may be left
+/// recursive, directly or through other rules: `sum := sum '+' num / num`
+/// then yields a left-nested tree. Without a context, left recursion never
+/// terminates.
+///
+/// A recursive body holds a reference to its own rule, so such a grammar is
+/// never freed; build it once and reuse it.
+pub struct Rule<T: Identifier, S: ?Sized + Input = str> {
+    body: Rc<OnceCell<Box<dyn Parser<T, S>>>>,
+}
+impl<T: Identifier, S: ?Sized + Input> Rule<T, S> {
+    pub fn new() -> Self {
+        Rule {
+            body: Rc::new(OnceCell::new()),
+        }
+    }
+    /// Sets the body of this rule. Panics if it already has one.
+    pub fn define(&self, p: Box<dyn Parser<T, S>>) {
+        if self.body.set(p).is_err() {
+            panic!("Rule was defined twice!");
+        }
+    }
+    /// A parser running this rule's body, usable before it is defined.
+    pub fn parser(&self) -> Box<dyn Parser<T, S>> {
+        Box::new(ParserRule {
+            body: self.body.clone(),
+        })
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Default for Rule<T, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct ParserRule<T, S: ?Sized + Input = str> {
+    pub(crate) body: Rc<OnceCell<Box<dyn Parser<T, S>>>>,
+}
+impl<T: Identifier, S: ?Sized + Input> Debug for ParserRule<T, S> {
+    // the body may contain this very parser, so it is not printed
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rule {:p}", Rc::as_ptr(&self.body))
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for ParserRule<T, S> {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        let p = match self.body.get() {
+            Some(p) => p.as_ref(),
+            None => panic!("Rule was run before being defined!"),
+        };
+        match input.ctx {
+            Some(ctx) => ctx
+                .memo
+                .grow(Rc::as_ptr(&self.body) as *const () as usize, p, input),
+            None => apply(p, input),
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// Streaming input.
+//
+// A `Stream` reads text from any `Read` in chunks and runs a record parser
+// over it again and again, like `multiple()` would, handing every record to
+// a callback. Nothing can backtrack into a record once the next one starts,
+// so the text of finished records is dropped from the buffer, and memory
+// stays bounded by the longest record plus two chunks, whatever the input
+// size.
+//
+// A record is only accepted once its parser ran without looking at the end
+// of the buffer, as the positions the parsers reached tell, so that reading
+// more input could not have changed the result. Otherwise more is read, at
+// least as much again as the record took so far, so that a long record is
+// only parsed a logarithmic number of times.
+
+use std::io::{self, Read};
+
+use crate::core::{Context, Identifier, NonTerminal, Parser, State};
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    /// A record failed to parse. `line` counts from the start of the stream,
+    /// and `msg` says what went wrong there, along with what was expected.
+    Parse {
+        line: usize,
+        column: usize,
+        msg: String,
+    },
+}
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Reading input failed: {}", e),
+            Self::Parse { line, column, msg } => write!(f, "[{}:{}]\t{}", line, column, msg),
+        }
+    }
+}
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse { .. } => None,
+        }
+    }
+}
+impl From<io::Error> for StreamError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Text read from a `Read` as the parser needs it.
+pub struct Stream<R: Read> {
+    reader: R,
+    // starts at the beginning of a line, so errors can show it whole, unless
+    // the records before on that line took more than a chunk
+    buffer: String,
+    // bytes of a character split between two reads
+    pending: Vec<u8>,
+    start: usize,
+    line: usize,
+    column: usize,
+    // columns of the current line dropped from the buffer
+    dropped: usize,
+    chunk: usize,
+    eof: bool,
+}
+impl<R: Read> Stream<R> {
+    pub fn new(reader: R) -> Self {
+        Stream {
+            reader,
+            buffer: String::new(),
+            pending: Vec::new(),
+            start: 0,
+            line: 0,
+            column: 0,
+            dropped: 0,
+            chunk: 8192,
+            eof: false,
+        }
+    }
+    /// Number of bytes read from the reader at once.
+    pub fn chunk(mut self, n: usize) -> Self {
+        assert!(n > 0, "Stream chunk must not be empty!");
+        self.chunk = n;
+        self
+    }
+    /// Number of bytes currently held in memory.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() + self.pending.len()
+    }
+    /// Runs `p` until the input is exhausted, calling `f` with every record
+    /// it parses, and returns how many there were.
+    ///
+    /// Each record is parsed with a fresh `Context`, so rules may be left
+    /// recursive and memoized results do not pile up. A record that fails,
+    /// or that matches nothing, stops the stream with an error. The spans of
+    /// a record count from the start of the buffer, which is the start of
+    /// the line it begins on unless the records before it on that line took
+    /// more than a chunk.
+    pub fn records<T: Identifier>(
+        &mut self,
+        p: &dyn Parser<T>,
+        mut f: impl FnMut(NonTerminal<'_, T>),
+    ) -> Result<usize, StreamError> {
+        let mut count = 0;
+        loop {
+            if self.start == self.buffer.len() {
+                if self.eof {
+                    return Ok(count);
+                }
+                self.fill()?;
+                continue;
+            }
+            let ctx = Context::new();
+            let input = State {
+                head: self.start,
+                column: self.column - self.dropped,
+                ..State::with_context(self.buffer.as_str(), &ctx)
+            };
+            let res = p.run(input);
+            if !self.eof && ctx.memo.reach() > self.buffer.len() {
+                drop(res);
+                let want = self.buffer.len() + (self.buffer.len() - self.start).max(self.chunk);
+                while !self.eof && self.buffer.len() < want {
+                    self.fill()?;
+                }
+                continue;
+            }
+            let (head, line, column) = match res {
+                Ok((n, s)) if s.head > self.start => {
+                    f(n);
+                    (s.head, s.line, s.column)
+                }
+                Ok(_) => {
+                    return Err(StreamError::Parse {
+                        line: self.line,
+                        column: self.column,
+                        msg: "Record parser matched nothing!".to_owned(),
+                    })
+                }
+                // the start of the line may be gone from the buffer, so the
+                // message leaves out the line and the position in it
+                Err((e, _)) => {
+                    let e = e.owned();
+                    return Err(StreamError::Parse {
+                        line: e.line + self.line,
+                        column: match e.line {
+                            0 => e.column + self.dropped,
+                            _ => e.column,
+                        },
+                        msg: match e.msg {
+                            Some(s) => format!("{}\n{}", s, e.expected),
+                            None => e.expected,
+                        },
+                    });
+                }
+            };
+            count += 1;
+            match self.buffer[..head].rfind('\n') {
+                Some(i) => {
+                    self.buffer.drain(..=i);
+                    self.start = head - i - 1;
+                    self.line += line;
+                    self.column = column;
+                    self.dropped = 0;
+                }
+                None => {
+                    self.start = head;
+                    self.column = column + self.dropped;
+                }
+            }
+            if self.start > self.chunk {
+                self.buffer.drain(..self.start);
+                self.start = 0;
+                self.dropped = self.column;
+            }
+        }
+    }
+    fn fill(&mut self) -> io::Result<()> {
+        let mut bytes = vec![0; self.chunk];
+        let n = loop {
+            match self.reader.read(&mut bytes) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                r => break r?,
+            }
+        };
+        if n == 0 {
+            self.eof = true;
+            if !self.pending.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Stream ended inside a character!",
+                ));
+            }
+            return Ok(());
+        }
+        self.pending.extend_from_slice(&bytes[..n]);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(s) => s.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        // checked just above
+        self.buffer
+            .push_str(std::str::from_utf8(&self.pending[..valid]).unwrap());
+        self.pending.drain(..valid);
+        Ok(())
+    }
+}
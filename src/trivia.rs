@@ -0,0 +1,101 @@
+// Trivia: whitespace and comments.
+//
+// `Trivia` describes what a grammar skips between its tokens, which
+// `ptrivia` parses, ready to be given to `skipping()`:
+//
+// trivia <- (space / line / block)*
+// line   <- start (!'\n' .)*           up to, not including, the newline
+// block  <- open (block / !close .)* close    blocks nest with nested()
+
+/// What counts as trivia. By default only whitespace, as
+/// `char::is_whitespace` tells.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    whitespace: bool,
+    line: Vec<String>,
+    block: Vec<(String, String)>,
+    nested: bool,
+}
+impl Trivia {
+    pub fn new() -> Self {
+        Trivia {
+            whitespace: true,
+            line: Vec::new(),
+            block: Vec::new(),
+            nested: false,
+        }
+    }
+    pub fn whitespace(mut self, on: bool) -> Self {
+        self.whitespace = on;
+        self
+    }
+    /// Comments from `start` to the end of the line, such as `//`.
+    pub fn line_comment(mut self, start: &str) -> Self {
+        assert!(!start.is_empty(), "empty comment start for Trivia!");
+        self.line.push(start.to_owned());
+        self
+    }
+    /// Comments from `open` to `close`, such as `/*` and `*/`.
+    pub fn block_comment(mut self, open: &str, close: &str) -> Self {
+        assert!(
+            !open.is_empty() && !close.is_empty(),
+            "empty comment delimiter for Trivia!"
+        );
+        self.block.push((open.to_owned(), close.to_owned()));
+        self
+    }
+    /// Whether block comments nest, so that `/* /* */ */` is one comment.
+    pub fn nested(mut self, on: bool) -> Self {
+        self.nested = on;
+        self
+    }
+
+    /// The length of the trivia `s` starts with, or where in `s` an unclosed
+    /// block comment opens.
+    pub(crate) fn scan(&self, s: &str) -> Result<usize, usize> {
+        let mut at = 0;
+        loop {
+            let rest = &s[at..];
+            if let Some(c) = rest
+                .chars()
+                .next()
+                .filter(|c| self.whitespace && c.is_whitespace())
+            {
+                at += c.len_utf8();
+            } else if self.line.iter().any(|l| rest.starts_with(l.as_str())) {
+                at += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some((open, close)) = self
+                .block
+                .iter()
+                .find(|(open, _)| rest.starts_with(open.as_str()))
+            {
+                at += self.block(rest, open, close).ok_or(at)?;
+            } else {
+                return Ok(at);
+            }
+        }
+    }
+    // the length of the block comment `s` starts with, if closed
+    fn block(&self, s: &str, open: &str, close: &str) -> Option<usize> {
+        let mut at = open.len();
+        let mut depth = 1;
+        while depth > 0 {
+            let rest = &s[at..];
+            if rest.starts_with(close) {
+                depth -= 1;
+                at += close.len();
+            } else if self.nested && rest.starts_with(open) {
+                depth += 1;
+                at += open.len();
+            } else {
+                at += rest.chars().next()?.len_utf8();
+            }
+        }
+        Some(at)
+    }
+}
+impl Default for Trivia {
+    fn default() -> Self {
+        Self::new()
+    }
+}
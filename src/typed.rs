@@ -0,0 +1,622 @@
+// Typed semantic actions.
+//
+// `map`, `and_then` and `fold` turn a parser into a `Typed` one, which yields
+// a value of any type instead of a `NonTerminal`. Typed parsers have their own
+// combinators, so a whole grammar can compute its result while parsing:
+//
+// let num = pin(['0', ..., '9']).atleast_once().catenate()
+//     .and_then(|n| leaf(&n).parse::<i64>().map_err(|_| "Number too large!"));
+// let sum = num.seq(pchar('+').map(|_| ()).seq(num).multiple()).map(...);
+//
+// `TypedRule` is the typed counterpart of `Rule`, for recursive grammars.
+
+use std::cell::OnceCell;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::combinators::skip;
+use crate::core::{
+    ErrorBacktrace, Identifier, Input, NonTerminal, ParseError, Parser, Skip, Skipped, Span, State,
+};
+use crate::memo::apply;
+
+type Failure<'a, T, S> = (ParseError<'a, T, S>, State<'a, S>);
+type TypedResult<'a, T, O, S> = Result<(O, State<'a, S>), Failure<'a, T, S>>;
+
+trait Action<T: Identifier, O, S: ?Sized + Input>: Debug {
+    #[allow(clippy::result_large_err)]
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, O, S>;
+}
+
+/// A parser yielding values of type `O`.
+pub struct Typed<T: Identifier, O, S: ?Sized + Input = str> {
+    recipe: Box<dyn Action<T, O, S>>,
+}
+impl<T: Identifier, O, S: ?Sized + Input> Debug for Typed<T, O, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.recipe)
+    }
+}
+impl<T: Identifier, O: 'static, S: ?Sized + Input> Typed<T, O, S> {
+    fn new(a: impl Action<T, O, S> + 'static) -> Self {
+        Typed {
+            recipe: Box::new(a),
+        }
+    }
+    #[allow(clippy::result_large_err)]
+    pub fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, O, S> {
+        self.recipe.run(input)
+    }
+    pub fn map<O2: 'static, F: Fn(O) -> O2 + 'static>(self, f: F) -> Typed<T, O2, S> {
+        Typed::new(TypedMap { recipe: self, f })
+    }
+    /// Like `map`, but `f` may reject the value, failing with its message.
+    pub fn and_then<O2: 'static, F: Fn(O) -> Result<O2, &'static str> + 'static>(
+        self,
+        f: F,
+    ) -> Typed<T, O2, S> {
+        Typed::new(TypedAndThen {
+            recipe: self,
+            f,
+            check: Check,
+        })
+    }
+    /// Runs this parser as often as possible, combining the values with `f`.
+    pub fn fold<A: 'static, I: Fn() -> A + 'static, F: Fn(A, O) -> A + 'static>(
+        self,
+        init: I,
+        f: F,
+    ) -> Typed<T, A, S> {
+        Typed::new(TypedFold {
+            recipe: self,
+            init,
+            f,
+        })
+    }
+    pub fn seq<O2: 'static>(self, p: Typed<T, O2, S>) -> Typed<T, (O, O2), S> {
+        Typed::new(TypedSeq {
+            first: self,
+            second: p,
+        })
+    }
+    pub fn or(self, p: Typed<T, O, S>) -> Typed<T, O, S> {
+        Typed::new(TypedChoice {
+            first: self,
+            second: p,
+        })
+    }
+    pub fn multiple(self) -> Typed<T, Vec<O>, S> {
+        self.fold(Vec::new, |mut v, o| {
+            v.push(o);
+            v
+        })
+    }
+    pub fn maybe(self) -> Typed<T, Option<O>, S> {
+        Typed::new(TypedMaybe { recipe: self })
+    }
+    pub fn label(self, ident: T) -> Typed<T, O, S> {
+        Typed::new(TypedLabeled {
+            recipe: self,
+            label: ident,
+        })
+    }
+    /// Skips `trivia` between the elements of every sequence and repetition
+    /// within this parser, and before and after it, like `Parser::skipping`.
+    pub fn skipping(self, trivia: Box<dyn Parser<T, S>>) -> Typed<T, O, S> {
+        Typed::new(TypedSkipping {
+            recipe: self,
+            trivia,
+        })
+    }
+    /// Parses this parser without skipping trivia inside it, like
+    /// `Parser::atomic`.
+    pub fn atomic(self) -> Typed<T, O, S> {
+        Typed::new(TypedAtomic { recipe: self })
+    }
+}
+
+/// A handle to a typed parser whose body is defined later, like `Rule`:
+/// `expr.define(num.or(pchar('(').map(|_| ()).seq(expr.parser())...))`.
+///
+/// Typed values are not memoized, so typed rules cannot be left recursive.
+/// A recursive body holds a reference to its own rule, so such a grammar is
+/// never freed; build it once and reuse it.
+pub struct TypedRule<T: Identifier, O, S: ?Sized + Input = str> {
+    body: Rc<OnceCell<Typed<T, O, S>>>,
+}
+impl<T: Identifier, O: 'static, S: ?Sized + Input> TypedRule<T, O, S> {
+    pub fn new() -> Self {
+        TypedRule {
+            body: Rc::new(OnceCell::new()),
+        }
+    }
+    /// Sets the body of this rule. Panics if it already has one.
+    pub fn define(&self, p: Typed<T, O, S>) {
+        if self.body.set(p).is_err() {
+            panic!("TypedRule was defined twice!");
+        }
+    }
+    /// A parser running this rule's body, usable before it is defined.
+    pub fn parser(&self) -> Typed<T, O, S> {
+        Typed::new(TypedRuleRef {
+            body: self.body.clone(),
+        })
+    }
+}
+impl<T: Identifier, O: 'static, S: ?Sized + Input> Default for TypedRule<T, O, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Semantic actions turning a parser's `NonTerminal` into a typed value.
+pub trait Semantic<T: Identifier, S: ?Sized + Input = str> {
+    fn map<O: 'static, F: for<'a> Fn(NonTerminal<'a, T, S>) -> O + 'static>(
+        self,
+        f: F,
+    ) -> Typed<T, O, S>;
+    /// Like `map`, but `f` may reject the tree, failing with its message.
+    fn and_then<
+        O: 'static,
+        F: for<'a> Fn(NonTerminal<'a, T, S>) -> Result<O, &'static str> + 'static,
+    >(
+        self,
+        f: F,
+    ) -> Typed<T, O, S>;
+    /// Runs the parser as often as possible, combining the trees with `f`.
+    fn fold<
+        A: 'static,
+        I: Fn() -> A + 'static,
+        F: for<'a> Fn(A, NonTerminal<'a, T, S>) -> A + 'static,
+    >(
+        self,
+        init: I,
+        f: F,
+    ) -> Typed<T, A, S>;
+}
+impl<T: Identifier, S: ?Sized + Input> Semantic<T, S> for Box<dyn Parser<T, S>> {
+    fn map<O: 'static, F: for<'a> Fn(NonTerminal<'a, T, S>) -> O + 'static>(
+        self,
+        f: F,
+    ) -> Typed<T, O, S> {
+        Typed::new(ParserMap { recipe: self, f })
+    }
+    fn and_then<
+        O: 'static,
+        F: for<'a> Fn(NonTerminal<'a, T, S>) -> Result<O, &'static str> + 'static,
+    >(
+        self,
+        f: F,
+    ) -> Typed<T, O, S> {
+        Typed::new(ParserMap { recipe: self, f }).and_then(|r| r)
+    }
+    fn fold<
+        A: 'static,
+        I: Fn() -> A + 'static,
+        F: for<'a> Fn(A, NonTerminal<'a, T, S>) -> A + 'static,
+    >(
+        self,
+        init: I,
+        f: F,
+    ) -> Typed<T, A, S> {
+        Typed::new(ParserFold {
+            recipe: self,
+            init,
+            f,
+        })
+    }
+}
+
+/// The leaf text of a tree, as produced by `catenate()` or a primitive.
+pub fn leaf<'a, T: Identifier>(n: &NonTerminal<'a, T>) -> &'a str {
+    match n {
+        NonTerminal::Leaf(s, _) => s,
+        NonTerminal::Node { children, .. } => leaf(children),
+        _ => "",
+    }
+}
+
+struct ParserMap<T: Identifier, F, S: ?Sized + Input> {
+    recipe: Box<dyn Parser<T, S>>,
+    f: F,
+}
+impl<T: Identifier, F, S: ?Sized + Input> Debug for ParserMap<T, F, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.recipe)
+    }
+}
+impl<T: Identifier, O, F: for<'a> Fn(NonTerminal<'a, T, S>) -> O, S: ?Sized + Input> Action<T, O, S>
+    for ParserMap<T, F, S>
+{
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, O, S> {
+        match apply(self.recipe.as_ref(), input) {
+            Ok((n, s)) => Ok(((self.f)(n), s)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+struct ParserFold<T: Identifier, I, F, S: ?Sized + Input> {
+    recipe: Box<dyn Parser<T, S>>,
+    init: I,
+    f: F,
+}
+impl<T: Identifier, I, F, S: ?Sized + Input> Debug for ParserFold<T, I, F, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} folded as often as possible", self.recipe)
+    }
+}
+impl<
+        T: Identifier,
+        A,
+        I: Fn() -> A,
+        F: for<'a> Fn(A, NonTerminal<'a, T, S>) -> A,
+        S: ?Sized + Input,
+    > Action<T, A, S> for ParserFold<T, I, F, S>
+{
+    #[allow(clippy::result_large_err)]
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, A, S> {
+        let mut acc = (self.init)();
+        let mut head = input;
+        for round in 0.. {
+            let next = match round {
+                0 => head,
+                _ => gap(head, input)?,
+            };
+            match next.backtrack(true, || apply(self.recipe.as_ref(), next.uncut())) {
+                Ok((n, s)) => {
+                    acc = (self.f)(acc, n);
+                    head = State {
+                        cut: input.cut,
+                        ..s
+                    };
+                }
+                Err((e, s)) if s.cut => return Err((e, State { cut: true, ..input })),
+                Err(_) => break,
+            }
+        }
+        Ok((acc, head))
+    }
+}
+
+struct TypedMap<T: Identifier, O, F, S: ?Sized + Input> {
+    recipe: Typed<T, O, S>,
+    f: F,
+}
+impl<T: Identifier, O, F, S: ?Sized + Input> Debug for TypedMap<T, O, F, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.recipe)
+    }
+}
+impl<T: Identifier, O, O2, F: Fn(O) -> O2, S: ?Sized + Input> Action<T, O2, S>
+    for TypedMap<T, O, F, S>
+{
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, O2, S> {
+        match self.recipe.recipe.run(input) {
+            Ok((o, s)) => Ok(((self.f)(o), s)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Stands in as the expected parser when a semantic action rejects a value.
+struct Check;
+impl Debug for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "value accepted by semantic action")
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for Check {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        Ok((NonTerminal::Empty(Span::at(&input)), input))
+    }
+}
+
+// Stands in as the expected parser when trivia between typed parsers fails.
+struct Gap;
+impl Debug for Gap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "trivia between typed parsers")
+    }
+}
+impl<T: Identifier, S: ?Sized + Input> Parser<T, S> for Gap {
+    fn to_dyn(self: Box<Self>) -> Box<dyn Parser<T, S>> {
+        self
+    }
+    fn run<'a>(
+        &'a self,
+        input: State<'a, S>,
+    ) -> Result<(NonTerminal<'a, T, S>, State<'a, S>), (ParseError<'a, T, S>, State<'a, S>)> {
+        Ok((NonTerminal::Empty(Span::at(&input)), input))
+    }
+}
+
+// skips the trivia at `at`, within a parse from `input`; typed parsers keep
+// no trees, so kept trivia is dropped too
+#[allow(clippy::result_large_err)]
+fn gap<'a, T: Identifier, S: ?Sized + Input>(
+    at: State<'a, S>,
+    input: State<'a, S>,
+) -> Result<State<'a, S>, Failure<'a, T, S>> {
+    at.skip_trivia(&Gap, &mut Vec::new())
+        .map_err(|e| (e, State { cut: true, ..input }))
+}
+
+struct TypedAndThen<T: Identifier, O, F, S: ?Sized + Input> {
+    recipe: Typed<T, O, S>,
+    f: F,
+    check: Check,
+}
+impl<T: Identifier, O, F, S: ?Sized + Input> Debug for TypedAndThen<T, O, F, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.recipe)
+    }
+}
+impl<T: Identifier, O, O2, F: Fn(O) -> Result<O2, &'static str>, S: ?Sized + Input> Action<T, O2, S>
+    for TypedAndThen<T, O, F, S>
+{
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, O2, S> {
+        match self.recipe.recipe.run(input) {
+            Ok((o, s)) => match (self.f)(o) {
+                Ok(o) => Ok((o, s)),
+                Err(msg) => Err((
+                    ParseError {
+                        location: (input.line_of(), input.line, input.column),
+                        head: input.head,
+                        expected: &self.check,
+                        backtrace: ErrorBacktrace::Empty,
+                        msg: Some(msg),
+                        ctx: input.ctx,
+                    },
+                    input,
+                )),
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+struct TypedFold<T: Identifier, O, I, F, S: ?Sized + Input> {
+    recipe: Typed<T, O, S>,
+    init: I,
+    f: F,
+}
+impl<T: Identifier, O, I, F, S: ?Sized + Input> Debug for TypedFold<T, O, I, F, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} folded as often as possible", self.recipe)
+    }
+}
+impl<T: Identifier, O, A, I: Fn() -> A, F: Fn(A, O) -> A, S: ?Sized + Input> Action<T, A, S>
+    for TypedFold<T, O, I, F, S>
+{
+    #[allow(clippy::result_large_err)]
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, A, S> {
+        let mut acc = (self.init)();
+        let mut head = input;
+        for round in 0.. {
+            let next = match round {
+                0 => head,
+                _ => gap(head, input)?,
+            };
+            match next.backtrack(true, || self.recipe.recipe.run(next.uncut())) {
+                Ok((o, s)) => {
+                    acc = (self.f)(acc, o);
+                    head = State {
+                        cut: input.cut,
+                        ..s
+                    };
+                }
+                Err((e, s)) if s.cut => return Err((e, State { cut: true, ..input })),
+                Err(_) => break,
+            }
+        }
+        Ok((acc, head))
+    }
+}
+
+struct TypedSeq<T: Identifier, O, O2, S: ?Sized + Input> {
+    first: Typed<T, O, S>,
+    second: Typed<T, O2, S>,
+}
+impl<T: Identifier, O, O2, S: ?Sized + Input> Debug for TypedSeq<T, O, O2, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sequence of\n- {:?}\n- {:?}", self.first, self.second)
+    }
+}
+impl<T: Identifier, O, O2, S: ?Sized + Input> Action<T, (O, O2), S> for TypedSeq<T, O, O2, S> {
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, (O, O2), S> {
+        let (o, s) = self.first.recipe.run(input)?;
+        let s = gap(s, input)?;
+        match self.second.recipe.run(s) {
+            Ok((o2, s)) => Ok(((o, o2), s)),
+            Err((e, s)) => Err((
+                e,
+                State {
+                    cut: s.cut,
+                    ..input
+                },
+            )),
+        }
+    }
+}
+
+struct TypedChoice<T: Identifier, O, S: ?Sized + Input> {
+    first: Typed<T, O, S>,
+    second: Typed<T, O, S>,
+}
+impl<T: Identifier, O, S: ?Sized + Input> Debug for TypedChoice<T, O, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Choice between\n- {:?}\n- {:?}", self.first, self.second)
+    }
+}
+impl<T: Identifier, O, S: ?Sized + Input> Action<T, O, S> for TypedChoice<T, O, S> {
+    #[allow(clippy::result_large_err)]
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, O, S> {
+        match input.backtrack(true, || self.first.recipe.run(input.uncut())) {
+            Ok((o, s)) => Ok((
+                o,
+                State {
+                    cut: input.cut,
+                    ..s
+                },
+            )),
+            Err((e, s)) if s.cut => Err((e, State { cut: true, ..input })),
+            Err(_) => match input.backtrack(true, || self.second.recipe.run(input.uncut())) {
+                Ok((o, s)) => Ok((
+                    o,
+                    State {
+                        cut: input.cut,
+                        ..s
+                    },
+                )),
+                Err((e, s)) => Err((
+                    e,
+                    State {
+                        cut: s.cut || input.cut,
+                        ..input
+                    },
+                )),
+            },
+        }
+    }
+}
+
+struct TypedMaybe<T: Identifier, O, S: ?Sized + Input> {
+    recipe: Typed<T, O, S>,
+}
+impl<T: Identifier, O, S: ?Sized + Input> Debug for TypedMaybe<T, O, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} or nothing", self.recipe)
+    }
+}
+impl<T: Identifier, O, S: ?Sized + Input> Action<T, Option<O>, S> for TypedMaybe<T, O, S> {
+    #[allow(clippy::result_large_err)]
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, Option<O>, S> {
+        match input.backtrack(true, || self.recipe.recipe.run(input.uncut())) {
+            Ok((o, s)) => Ok((
+                Some(o),
+                State {
+                    cut: input.cut,
+                    ..s
+                },
+            )),
+            Err((e, s)) if s.cut => Err((e, State { cut: true, ..input })),
+            Err(_) => Ok((None, input)),
+        }
+    }
+}
+
+struct TypedLabeled<T: Identifier, O, S: ?Sized + Input> {
+    recipe: Typed<T, O, S>,
+    label: T,
+}
+impl<T: Identifier, O, S: ?Sized + Input> Debug for TypedLabeled<T, O, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.recipe)
+    }
+}
+impl<T: Identifier, O, S: ?Sized + Input> Action<T, O, S> for TypedLabeled<T, O, S> {
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, O, S> {
+        match self.recipe.recipe.run(input) {
+            Err((mut e, s)) => {
+                let b = e.backtrace;
+                e.backtrace = ErrorBacktrace::Node {
+                    identifier: self.label,
+                    next: Box::new(b),
+                };
+                Err((e, s))
+            }
+            o => o,
+        }
+    }
+}
+
+struct TypedSkipping<T: Identifier, O, S: ?Sized + Input> {
+    recipe: Typed<T, O, S>,
+    trivia: Box<dyn Parser<T, S>>,
+}
+impl<T: Identifier, O, S: ?Sized + Input> Debug for TypedSkipping<T, O, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}, skipping {:?}", self.recipe, self.trivia)
+    }
+}
+impl<T: Identifier, O, S: ?Sized + Input> Action<T, O, S> for TypedSkipping<T, O, S> {
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, O, S> {
+        let restore = |s: State<'a, S>| State {
+            trivia: input.trivia,
+            ..s
+        };
+        let skipping = State {
+            trivia: Some(self),
+            ..input
+        };
+        let head = gap(skipping, input)?;
+        let (o, s) = match self.recipe.recipe.run(head) {
+            Ok(r) => r,
+            Err((e, s)) => return Err((e, restore(s))),
+        };
+        Ok((o, restore(gap(s, input)?)))
+    }
+}
+impl<T: Identifier, O, S: ?Sized + Input> Skip<S> for TypedSkipping<T, O, S> {
+    fn skip<'a>(&'a self, input: State<'a, S>) -> Skipped<'a, S> {
+        skip(self.trivia.as_ref(), false, input)
+    }
+}
+
+struct TypedAtomic<T: Identifier, O, S: ?Sized + Input> {
+    recipe: Typed<T, O, S>,
+}
+impl<T: Identifier, O, S: ?Sized + Input> Debug for TypedAtomic<T, O, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} as a whole", self.recipe)
+    }
+}
+impl<T: Identifier, O, S: ?Sized + Input> Action<T, O, S> for TypedAtomic<T, O, S> {
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, O, S> {
+        let inside = State {
+            trivia: None,
+            ..input
+        };
+        match self.recipe.recipe.run(inside) {
+            Ok((o, s)) => Ok((
+                o,
+                State {
+                    trivia: input.trivia,
+                    ..s
+                },
+            )),
+            Err((e, s)) => Err((
+                e,
+                State {
+                    trivia: input.trivia,
+                    ..s
+                },
+            )),
+        }
+    }
+}
+
+struct TypedRuleRef<T: Identifier, O, S: ?Sized + Input> {
+    body: Rc<OnceCell<Typed<T, O, S>>>,
+}
+impl<T: Identifier, O, S: ?Sized + Input> Debug for TypedRuleRef<T, O, S> {
+    // the body may contain this very parser, so it is not printed
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "typed rule {:p}", Rc::as_ptr(&self.body))
+    }
+}
+impl<T: Identifier, O, S: ?Sized + Input> Action<T, O, S> for TypedRuleRef<T, O, S> {
+    fn run<'a>(&'a self, input: State<'a, S>) -> TypedResult<'a, T, O, S> {
+        match self.body.get() {
+            Some(p) => p.recipe.run(input),
+            None => panic!("TypedRule was run before being defined!"),
+        }
+    }
+}
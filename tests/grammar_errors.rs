@@ -0,0 +1,7 @@
+// Grammars `grammar!` rejects at compile time, with the errors it reports.
+
+#[test]
+fn grammar_errors() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
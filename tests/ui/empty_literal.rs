@@ -0,0 +1,8 @@
+use page::grammar;
+
+grammar! {
+    enum G;
+    empty <- r"" ;
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use page::grammar;
+
+grammar! {
+    enum G;
+    sum <- num ("+" num)* ;
+    num <- ['0'-'9']+ + ;
+}
+
+fn main() {}